@@ -23,6 +23,55 @@ impl fmt::Display for Literal {
     }
 }
 
+impl Literal {
+    fn hash_structure(&self, state: &mut Fnv1a) {
+        match self {
+            Literal::Number(n) => {
+                state.write_u8(0);
+                state.write_u64(n.to_bits());
+            },
+            Literal::String(s) => {
+                state.write_u8(1);
+                state.write_str(s);
+            },
+            Literal::True => state.write_u8(2),
+            Literal::False => state.write_u8(3),
+            Literal::Nil => state.write_u8(4)
+        }
+    }
+}
+
+// 64-bit FNV-1a, used for structural hashes: unlike DefaultHasher, its
+// definition is fixed, so hashes stay the same across Rust versions
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u8(&mut self, n: u8) {
+        self.write(&[n]);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    // strings are prefixed by their length, so "ab" + "c" != "a" + "bc"
+    fn write_str(&mut self, s: &str) {
+        self.write_u64(s.len() as u64);
+        self.write(s.as_bytes());
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum UnOp {
     Minus,
@@ -60,6 +109,9 @@ pub enum BinOp {
     Minus,
     Mult,
     Div,
+    Mod,
+    Min,
+    Max,
 }
 
 impl BinOp {
@@ -75,6 +127,9 @@ impl BinOp {
             TokenType::Minus => Some(BinOp::Minus),
             TokenType::Slash => Some(BinOp::Div),
             TokenType::Star => Some(BinOp::Mult),
+            TokenType::Percent => Some(BinOp::Mod),
+            TokenType::LessQuestion => Some(BinOp::Min),
+            TokenType::GreaterQuestion => Some(BinOp::Max),
             _ => None
         }
     }
@@ -92,7 +147,26 @@ impl fmt::Display for BinOp {
             BinOp::Plus => write!(f, "+"),
             BinOp::Minus => write!(f, "-"),
             BinOp::Mult => write!(f, "*"),
-            BinOp::Div => write!(f, "/")
+            BinOp::Div => write!(f, "/"),
+            BinOp::Mod => write!(f, "%"),
+            BinOp::Min => write!(f, "<?"),
+            BinOp::Max => write!(f, ">?")
+        }
+    }
+}
+
+// part of an interpolated string, either text or an embedded expression
+#[derive(Debug, PartialEq)]
+pub enum InterpolationPart {
+    Literal(String),
+    Expr(Expr),
+}
+
+impl fmt::Display for InterpolationPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpolationPart::Literal(s) => write!(f, "\"{}\"", s),
+            InterpolationPart::Expr(e) => write!(f, "{}", e)
         }
     }
 }
@@ -103,6 +177,7 @@ pub enum Expr {
     Unary(UnOp, Box<Expr>),
     Binary(BinOp, Box<Expr>, Box<Expr>),
     Grouping(Box<Expr>),
+    Interpolation(Vec<InterpolationPart>),
 }
 
 impl Expr {
@@ -146,6 +221,73 @@ impl Expr {
         Expr::Unary(op, Box::new(e))
     }
 
+    // hash of the structure of the expression, usable as a cache key; it
+    // depends only on the nodes, so the same code always has the same hash
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        self.hash_structure(&mut hasher);
+        hasher.0
+    }
+
+    // each node is hashed as a tag byte for its variant followed by its
+    // contents (the tags must not change, or cached hashes become invalid)
+    fn hash_structure(&self, state: &mut Fnv1a) {
+        match self {
+            Expr::Literal(l) => {
+                state.write_u8(0);
+                l.hash_structure(state);
+            },
+            Expr::Unary(op, e) => {
+                state.write_u8(1);
+                state.write_u8(match op {
+                    UnOp::Minus => 0,
+                    UnOp::Not => 1
+                });
+                e.hash_structure(state);
+            },
+            Expr::Binary(op, e1, e2) => {
+                state.write_u8(2);
+                state.write_u8(match op {
+                    BinOp::Equal => 0,
+                    BinOp::NotEqual => 1,
+                    BinOp::Lt => 2,
+                    BinOp::LtEqual => 3,
+                    BinOp::Gt => 4,
+                    BinOp::GtEqual => 5,
+                    BinOp::Plus => 6,
+                    BinOp::Minus => 7,
+                    BinOp::Mult => 8,
+                    BinOp::Div => 9,
+                    BinOp::Mod => 10,
+                    BinOp::Min => 11,
+                    BinOp::Max => 12
+                });
+                e1.hash_structure(state);
+                e2.hash_structure(state);
+            },
+            Expr::Grouping(e) => {
+                state.write_u8(3);
+                e.hash_structure(state);
+            },
+            Expr::Interpolation(parts) => {
+                state.write_u8(4);
+                state.write_u64(parts.len() as u64);
+                for part in parts {
+                    match part {
+                        InterpolationPart::Literal(s) => {
+                            state.write_u8(0);
+                            state.write_str(s);
+                        },
+                        InterpolationPart::Expr(e) => {
+                            state.write_u8(1);
+                            e.hash_structure(state);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     pub fn unary_from_token(op_tok: TokenType, e: Expr) -> Expr {
         let op = match UnOp::from_token_type(op_tok) {
             Some(uop) => uop,
@@ -161,7 +303,34 @@ impl fmt::Display for Expr {
             Expr::Literal(l) => write!(f, "{}", l),
             Expr::Unary(op, exp) => write!(f, "({} {})", op, exp),
             Expr::Binary(op, e1, e2) => write!(f, "({} {} {})", op, e1, e2),
-            Expr::Grouping(e) => write!(f, "(group {})", e)
+            Expr::Grouping(e) => write!(f, "(group {})", e),
+            Expr::Interpolation(parts) => {
+                write!(f, "(interpolate")?;
+                for part in parts {
+                    write!(f, " {}", part)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+// remove all grouping nodes from an expression; after parsing, precedence
+// is encoded in the structure of the tree, so groupings are redundant
+pub fn normalize(expr: Expr) -> Expr {
+    match expr {
+        Expr::Literal(l) => Expr::Literal(l),
+        Expr::Grouping(e) => normalize(*e),
+        Expr::Unary(op, e) => Expr::unary(op, normalize(*e)),
+        Expr::Binary(op, e1, e2) => Expr::binary(op, normalize(*e1), normalize(*e2)),
+        Expr::Interpolation(parts) => {
+            let parts = parts.into_iter()
+                .map(|part| match part {
+                    InterpolationPart::Expr(e) => InterpolationPart::Expr(normalize(e)),
+                    literal => literal
+                })
+                .collect();
+            Expr::Interpolation(parts)
         }
     }
 }
@@ -186,3 +355,40 @@ fn test_ast_display() {
 
     assert_eq!(format!("{}", exp), "(* (neg 123) (group 45.67))");
 }
+
+#[test]
+fn test_normalize() {
+    use crate::parser::Parser;
+
+    let expr = Parser::new("(1 + 2)").parse().unwrap();
+    assert_eq!(normalize(expr),
+               Expr::binary(BinOp::Plus, Expr::number_literal(1.0), Expr::number_literal(2.0)));
+
+    let expr = Parser::new("-((3)) * (4 - (5))").parse().unwrap();
+    let expected = Expr::binary(BinOp::Mult,
+                                Expr::unary(UnOp::Minus, Expr::number_literal(3.0)),
+                                Expr::binary(BinOp::Minus,
+                                             Expr::number_literal(4.0),
+                                             Expr::number_literal(5.0)));
+    assert_eq!(normalize(expr), expected);
+}
+
+#[test]
+fn test_structural_hash() {
+    use crate::parser::Parser;
+
+    let hash = |source: &str| Parser::new(source).parse().unwrap().structural_hash();
+
+    // same structure on different lines and columns
+    assert_eq!(hash("1 + 2 * 3"), hash("\n\n  1 +\n2\n * 3"));
+    assert_eq!(hash("\"a ${-4}\""), hash("\n\"a ${ -4 }\""));
+
+    assert_ne!(hash("1 + 2 * 3"), hash("1 + 2 * 4"));
+    assert_ne!(hash("1 + 2 * 3"), hash("(1 + 2) * 3"));
+    assert_ne!(hash("1 - 2"), hash("1 + 2"));
+    assert_ne!(hash("\"1\""), hash("1"));
+
+    // the hash is fixed, it doesn't depend on the Rust version
+    assert_eq!(Expr::nil_literal().structural_hash(), 0x08328407b4eb6921);
+    assert_eq!(hash("1 + 2 * 3"), 0xf24b03b66f82255e);
+}
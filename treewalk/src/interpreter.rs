@@ -1,4 +1,11 @@
-#[derive(Debug, PartialEq)]
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::mem;
+
+// values implement Eq and Hash so they can be used as map keys;
+// numbers are compared by their bit patterns (see number_key)
+#[derive(Debug)]
 pub enum Value {
     Nil,
     Number(f64),
@@ -6,19 +13,151 @@ pub enum Value {
     String(String)
 }
 
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Number(n1), Value::Number(n2)) => number_key(*n1) == number_key(*n2),
+            (Value::Boolean(b1), Value::Boolean(b2)) => b1 == b2,
+            (Value::String(s1), Value::String(s2)) => s1 == s2,
+            _ => false
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Value::Nil => (),
+            Value::Number(n) => number_key(*n).hash(state),
+            Value::Boolean(b) => b.hash(state),
+            Value::String(s) => s.hash(state)
+        }
+    }
+}
+
+// key used to compare and hash numbers: all NaNs collapse into a single
+// key (so NaN can be found again in a map) and -0.0 is the same as 0.0
+fn number_key(n: f64) -> u64 {
+    if n.is_nan() {
+        f64::NAN.to_bits()
+    } else if n == 0.0 {
+        0
+    } else {
+        n.to_bits()
+    }
+}
+
 use crate::ast::Expr;
 use crate::ast::Literal;
 use crate::ast::UnOp;
 use crate::ast::BinOp;
+use crate::ast::InterpolationPart;
+
+// how numbers are displayed: always in plain decimal notation, or in
+// scientific notation when the magnitude is below small or at least large
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumberFormat {
+    Plain,
+    Scientific { small: f64, large: f64 },
+}
+
+impl NumberFormat {
+    // scientific notation with the same thresholds as JavaScript
+    pub fn scientific() -> NumberFormat {
+        NumberFormat::Scientific { small: 1e-6, large: 1e21 }
+    }
+}
 
-pub fn eval(exp: &Expr) -> Value {
+// write the printed representation of a value directly into a buffer
+pub fn write_value(f: &mut impl fmt::Write, v: &Value) -> fmt::Result {
+    write_value_with(f, v, NumberFormat::Plain)
+}
+
+pub fn write_value_with(f: &mut impl fmt::Write, v: &Value, format: NumberFormat) -> fmt::Result {
+    match v {
+        Value::Nil => f.write_str("nil"),
+        Value::Number(n) => write_number(f, *n, format),
+        Value::Boolean(b) => write!(f, "{}", b),
+        Value::String(s) => f.write_str(s)
+    }
+}
+
+fn write_number(f: &mut impl fmt::Write, n: f64, format: NumberFormat) -> fmt::Result {
+    match format {
+        NumberFormat::Scientific { small, large }
+            if n != 0.0 && (n.abs() < small || n.abs() >= large) => write!(f, "{:e}", n),
+        _ => write!(f, "{}", n)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_value(f, self)
+    }
+}
+
+pub fn stringify(v: &Value) -> String {
+    stringify_with(v, NumberFormat::Plain)
+}
+
+pub fn stringify_with(v: &Value, format: NumberFormat) -> String {
+    let mut res = String::new();
+    write_value_with(&mut res, v, format).expect("writing to a String should not fail");
+    res
+}
+
+// error raised while evaluating an expression, e.g. when an operand has the wrong type
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError {
+    pub message: String
+}
+
+impl RuntimeError {
+    pub fn new(message: String) -> RuntimeError {
+        RuntimeError { message }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Runtime error: {}", self.message)
+    }
+}
+
+pub type EvalResult = Result<Value, RuntimeError>;
+
+pub fn eval(exp: &Expr) -> EvalResult {
+    eval_with(exp, NumberFormat::Plain)
+}
+
+// the number format is used to convert numbers to strings in interpolations,
+// so they are shown in the same way as the values printed by the interpreter
+pub fn eval_with(exp: &Expr, format: NumberFormat) -> EvalResult {
     match exp {
-        Expr::Literal(l) => eval_literal(l),
-        Expr::Grouping(e) => eval(e),
-        Expr::Unary(op, e) => eval_unary(op, e),
-        Expr::Binary(op, e1, e2) => eval_binary(op, e1, e2),
-        _ => Value::Nil
+        Expr::Literal(l) => Ok(eval_literal(l)),
+        Expr::Grouping(e) => eval_with(e, format),
+        Expr::Unary(op, e) => eval_unary(op, e, format),
+        Expr::Binary(op, e1, e2) => eval_binary(op, e1, e2, format),
+        Expr::Interpolation(parts) => eval_interpolation(parts, format)
+    }
+}
+
+fn eval_interpolation(parts: &[InterpolationPart], format: NumberFormat) -> EvalResult {
+    let mut res = String::new();
+    for part in parts {
+        match part {
+            InterpolationPart::Literal(s) => res.push_str(s),
+            InterpolationPart::Expr(e) => {
+                write_value_with(&mut res, &eval_with(e, format)?, format)
+                    .expect("writing to a String should not fail")
+            }
+        }
     }
+    Ok(Value::String(res))
 }
 
 fn eval_literal(literal: &Literal) -> Value {
@@ -31,32 +170,109 @@ fn eval_literal(literal: &Literal) -> Value {
     }
 }
 
-fn eval_unary(op: &UnOp, e: &Expr) -> Value {
+fn eval_unary(op: &UnOp, e: &Expr, format: NumberFormat) -> EvalResult {
     match op {
-        UnOp::Minus => minus(&eval(e)),
-        UnOp::Not => negate(&eval(e))
+        UnOp::Minus => minus(&eval_with(e, format)?),
+        UnOp::Not => Ok(negate(&eval_with(e, format)?))
     }
 }
 
-fn eval_binary(op: &BinOp, e1: &Expr, e2: &Expr) -> Value {
+fn eval_binary(op: &BinOp, e1: &Expr, e2: &Expr, format: NumberFormat) -> EvalResult {
+    let v1 = eval_with(e1, format)?;
+    let v2 = eval_with(e2, format)?;
     match op {
-        BinOp::Plus => Value::Nil,
-        BinOp::Minus => Value::Nil,
-        BinOp::Mult => Value::Nil,
-        BinOp::Div => Value::Nil,
-        BinOp::Gt => Value::Nil,
-        BinOp::GtEqual => Value::Nil,
-        BinOp::Lt => Value::Nil,
-        BinOp::LtEqual => Value::Nil,
-        BinOp::Equal => Value::Nil,
-        BinOp::NotEqual => Value::Nil
+        BinOp::Plus => plus(&v1, &v2),
+        BinOp::Minus => arithmetic(op, &v1, &v2, |n1, n2| n1 - n2),
+        BinOp::Mult => arithmetic(op, &v1, &v2, |n1, n2| n1 * n2),
+        // division follows IEEE 754: x / 0 is inf or -inf, and 0 / 0 is NaN
+        BinOp::Div => arithmetic(op, &v1, &v2, |n1, n2| n1 / n2),
+        BinOp::Mod => modulo(&v1, &v2),
+        BinOp::Gt => comparison(op, &v1, &v2, |n1, n2| n1 > n2),
+        BinOp::GtEqual => comparison(op, &v1, &v2, |n1, n2| n1 >= n2),
+        BinOp::Lt => comparison(op, &v1, &v2, |n1, n2| n1 < n2),
+        BinOp::LtEqual => comparison(op, &v1, &v2, |n1, n2| n1 <= n2),
+        BinOp::Equal => Ok(Value::Boolean(is_equal(&v1, &v2))),
+        BinOp::NotEqual => Ok(Value::Boolean(!is_equal(&v1, &v2))),
+        BinOp::Min => min(&v1, &v2),
+        BinOp::Max => max(&v1, &v2)
+    }
+}
+
+// equality in Lox: values of different types are never equal, and numbers
+// follow IEEE 754 (NaN != NaN), unlike the PartialEq used for map keys
+pub fn is_equal(v1: &Value, v2: &Value) -> bool {
+    match (v1, v2) {
+        (Value::Nil, Value::Nil) => true,
+        (Value::Number(n1), Value::Number(n2)) => n1 == n2,
+        (Value::Boolean(b1), Value::Boolean(b2)) => b1 == b2,
+        (Value::String(s1), Value::String(s2)) => s1 == s2,
+        _ => false
     }
 }
 
-fn minus(v: &Value) -> Value {
+fn operands_error(op: &BinOp, v1: &Value, v2: &Value) -> RuntimeError {
+    RuntimeError::new(format!("Operands of {} must be numbers: {:?}, {:?}", op, v1, v2))
+}
+
+// + adds numbers and concatenates strings
+fn plus(v1: &Value, v2: &Value) -> EvalResult {
+    match (v1, v2) {
+        (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1 + n2)),
+        (Value::String(s1), Value::String(s2)) => Ok(Value::String(format!("{}{}", s1, s2))),
+        _ => Err(RuntimeError::new(
+            format!("Operands of + must be two numbers or two strings: {:?}, {:?}", v1, v2)))
+    }
+}
+
+fn arithmetic(op: &BinOp, v1: &Value, v2: &Value, f: fn(f64, f64) -> f64) -> EvalResult {
+    match (v1, v2) {
+        (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(f(*n1, *n2))),
+        _ => Err(operands_error(op, v1, v2))
+    }
+}
+
+// only numbers can be compared with <, <=, > and >=
+fn comparison(op: &BinOp, v1: &Value, v2: &Value, f: fn(f64, f64) -> bool) -> EvalResult {
+    match (v1, v2) {
+        (Value::Number(n1), Value::Number(n2)) => Ok(Value::Boolean(f(*n1, *n2))),
+        _ => Err(operands_error(op, v1, v2))
+    }
+}
+
+// % is the floored modulo: the result has the sign of the divisor, so
+// -7 % 3 == 2 and 7 % -3 == -2 (as in Python); x % 0 is NaN
+fn modulo(v1: &Value, v2: &Value) -> EvalResult {
+    match (v1, v2) {
+        (Value::Number(n1), Value::Number(n2)) => {
+            let r = n1.rem_euclid(*n2);   // always non-negative
+            if *n2 < 0.0 && r != 0.0 {
+                Ok(Value::Number(r + n2))
+            } else {
+                Ok(Value::Number(r))
+            }
+        },
+        _ => Err(operands_error(&BinOp::Mod, v1, v2))
+    }
+}
+
+fn min(v1: &Value, v2: &Value) -> EvalResult {
+    match (v1, v2) {
+        (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1.min(*n2))),
+        _ => Err(operands_error(&BinOp::Min, v1, v2))
+    }
+}
+
+fn max(v1: &Value, v2: &Value) -> EvalResult {
+    match (v1, v2) {
+        (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1.max(*n2))),
+        _ => Err(operands_error(&BinOp::Max, v1, v2))
+    }
+}
+
+fn minus(v: &Value) -> EvalResult {
     match v {
-        Value::Number(n) => Value::Number(- *n),
-        _ => panic!("Tried to invert sign of a non-numeric value: {:?}", v)
+        Value::Number(n) => Ok(Value::Number(- *n)),
+        _ => Err(RuntimeError::new(format!("Tried to invert sign of a non-numeric value: {:?}", v)))
     }
 }
 
@@ -68,3 +284,229 @@ fn negate(v: &Value) -> Value {
         _ => Value::Boolean(false)
     }
 }
+
+
+// tests
+
+#[test]
+fn test_values_as_map_keys() {
+    use std::collections::HashMap;
+
+    let mut map = HashMap::new();
+    map.insert(Value::Number(42.0), "number");
+    map.insert(Value::String("key".to_string()), "string");
+    map.insert(Value::Boolean(true), "boolean");
+    map.insert(Value::Nil, "nil");
+
+    assert_eq!(map.get(&Value::Number(42.0)), Some(&"number"));
+    assert_eq!(map.get(&Value::String("key".to_string())), Some(&"string"));
+    assert_eq!(map.get(&Value::Boolean(true)), Some(&"boolean"));
+    assert_eq!(map.get(&Value::Nil), Some(&"nil"));
+    assert_eq!(map.get(&Value::String("42".to_string())), None);
+    assert_eq!(map.get(&Value::Boolean(false)), None);
+}
+
+#[test]
+fn test_number_keys() {
+    use std::collections::HashMap;
+    use crate::parser::Parser;
+
+    let mut map = HashMap::new();
+    map.insert(eval(&Parser::new("1").parse().unwrap()).unwrap(), "one");
+    map.insert(eval(&Parser::new("1.0").parse().unwrap()).unwrap(), "one point zero");
+
+    // 1 and 1.0 are the same number, so the second insert replaces the first
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.get(&Value::Number(1.0)), Some(&"one point zero"));
+
+    map.insert(Value::Number(f64::NAN), "nan");
+    assert_eq!(map.get(&Value::Number(f64::NAN)), Some(&"nan"));
+
+    map.insert(Value::Number(0.0), "zero");
+    assert_eq!(map.get(&Value::Number(-0.0)), Some(&"zero"));
+}
+
+#[test]
+fn test_min_max() {
+    use crate::parser::Parser;
+
+    assert_eq!(eval(&Parser::new("3 <? 5").parse().unwrap()), Ok(Value::Number(3.0)));
+    assert_eq!(eval(&Parser::new("3 >? 5").parse().unwrap()), Ok(Value::Number(5.0)));
+    assert_eq!(eval(&Parser::new("-2 <? 1 <? -7").parse().unwrap()), Ok(Value::Number(-7.0)));
+}
+
+#[test]
+fn test_min_non_number() {
+    use crate::parser::Parser;
+
+    let err = eval(&Parser::new("\"a\" <? 5").parse().unwrap()).unwrap_err();
+    assert!(err.message.starts_with("Operands of <? must be numbers"));
+}
+
+#[test]
+fn test_modulo() {
+    use crate::parser::Parser;
+
+    assert_eq!(eval(&Parser::new("7 % 3").parse().unwrap()), Ok(Value::Number(1.0)));
+    assert_eq!(eval(&Parser::new("-7 % 3").parse().unwrap()), Ok(Value::Number(2.0)));
+    assert_eq!(eval(&Parser::new("7 % -3").parse().unwrap()), Ok(Value::Number(-2.0)));
+    assert_eq!(eval(&Parser::new("-7 % -3").parse().unwrap()), Ok(Value::Number(-1.0)));
+    assert_eq!(eval(&Parser::new("6 % -3").parse().unwrap()), Ok(Value::Number(0.0)));
+    assert_eq!(eval(&Parser::new("7.5 % 2").parse().unwrap()), Ok(Value::Number(1.5)));
+}
+
+#[test]
+fn test_write_value() {
+    let values = vec![
+        Value::Nil,
+        Value::Number(3.0),
+        Value::Number(-0.25),
+        Value::Boolean(false),
+        Value::String("some text".to_string()),
+    ];
+
+    let mut buffer = String::new();
+    for v in &values {
+        write_value(&mut buffer, v).unwrap();
+        buffer.push(' ');
+    }
+
+    let expected: Vec<String> = values.iter().map(stringify).collect();
+    assert_eq!(buffer.trim_end(), expected.join(" "));
+    assert_eq!(buffer, "nil 3 -0.25 false some text ");
+}
+
+#[test]
+fn test_interpolation() {
+    use crate::parser::Parser;
+
+    let expr = Parser::new("\"min: ${3 <? 5}, max: ${3 >? 5}\"").parse().unwrap();
+    assert_eq!(eval(&expr), Ok(Value::String("min: 3, max: 5".to_string())));
+
+    // interpolated string inside an interpolation
+    let expr = Parser::new("\"a ${\"b ${!nil} c\"} d\"").parse().unwrap();
+    assert_eq!(eval(&expr), Ok(Value::String("a b true c d".to_string())));
+
+    // numbers are converted with the given number format, also when nested
+    let expr = Parser::new("\"${1 / 10000000}, ${\"${2 * 10000000000000000000000}\"}\"").parse().unwrap();
+    assert_eq!(eval(&expr), Ok(Value::String("0.0000001, 20000000000000000000000".to_string())));
+    assert_eq!(eval_with(&expr, NumberFormat::scientific()),
+               Ok(Value::String("1e-7, 2e22".to_string())));
+}
+
+#[test]
+fn test_number_format() {
+    let tiny = Value::Number(1e-10);
+    let huge = Value::Number(1.5e22);
+    let normal = Value::Number(1234.5);
+
+    assert_eq!(stringify(&tiny), "0.0000000001");
+    assert_eq!(stringify(&huge), "15000000000000000000000");
+    assert_eq!(stringify(&normal), "1234.5");
+
+    let scientific = NumberFormat::scientific();
+    assert_eq!(stringify_with(&tiny, scientific), "1e-10");
+    assert_eq!(stringify_with(&huge, scientific), "1.5e22");
+    assert_eq!(stringify_with(&normal, scientific), "1234.5");
+    assert_eq!(stringify_with(&Value::Number(0.0), scientific), "0");
+
+    let custom = NumberFormat::Scientific { small: 0.01, large: 1000.0 };
+    assert_eq!(stringify_with(&Value::Number(0.005), custom), "5e-3");
+    assert_eq!(stringify_with(&normal, custom), "1.2345e3");
+    assert_eq!(stringify_with(&Value::Number(-250.0), custom), "-250");
+}
+
+#[test]
+fn test_arithmetic() {
+    use crate::parser::Parser;
+
+    assert_eq!(eval(&Parser::new("1 + 2").parse().unwrap()), Ok(Value::Number(3.0)));
+    assert_eq!(eval(&Parser::new("10 - 4 * 2").parse().unwrap()), Ok(Value::Number(2.0)));
+    assert_eq!(eval(&Parser::new("(1 + 2) * (10 - (3 - 1)) / 4").parse().unwrap()), Ok(Value::Number(6.0)));
+    assert_eq!(eval(&Parser::new("-(2 * 3) + 10 / 4").parse().unwrap()), Ok(Value::Number(-3.5)));
+    assert_eq!(eval(&Parser::new("\"ab\" + \"cd\"").parse().unwrap()), Ok(Value::String("abcd".to_string())));
+}
+
+#[test]
+fn test_division_by_zero() {
+    use crate::parser::Parser;
+
+    assert_eq!(eval(&Parser::new("1 / 0").parse().unwrap()), Ok(Value::Number(f64::INFINITY)));
+    assert_eq!(eval(&Parser::new("-1 / 0").parse().unwrap()), Ok(Value::Number(f64::NEG_INFINITY)));
+    match eval(&Parser::new("0 / 0").parse().unwrap()).unwrap() {
+        Value::Number(n) => assert!(n.is_nan()),
+        v => panic!("Expected a number, got {:?}", v)
+    }
+}
+
+#[test]
+fn test_arithmetic_non_number() {
+    use crate::parser::Parser;
+
+    let err = eval(&Parser::new("2 * \"a\"").parse().unwrap()).unwrap_err();
+    assert!(err.message.starts_with("Operands of * must be numbers"));
+}
+
+#[test]
+fn test_comparison() {
+    use crate::parser::Parser;
+
+    assert_eq!(eval(&Parser::new("3 < 5").parse().unwrap()), Ok(Value::Boolean(true)));
+    assert_eq!(eval(&Parser::new("5 < 3").parse().unwrap()), Ok(Value::Boolean(false)));
+    assert_eq!(eval(&Parser::new("5 <= 5").parse().unwrap()), Ok(Value::Boolean(true)));
+    assert_eq!(eval(&Parser::new("5 > 5").parse().unwrap()), Ok(Value::Boolean(false)));
+    assert_eq!(eval(&Parser::new("5 >= 5").parse().unwrap()), Ok(Value::Boolean(true)));
+    assert_eq!(eval(&Parser::new("1 + 1 > 1").parse().unwrap()), Ok(Value::Boolean(true)));
+}
+
+#[test]
+fn test_comparison_non_number() {
+    use crate::parser::Parser;
+
+    let err = eval(&Parser::new("\"a\" < 2").parse().unwrap()).unwrap_err();
+    assert!(err.message.starts_with("Operands of < must be numbers"));
+}
+
+#[test]
+fn test_equality() {
+    use crate::parser::Parser;
+
+    assert_eq!(eval(&Parser::new("nil == nil").parse().unwrap()), Ok(Value::Boolean(true)));
+    assert_eq!(eval(&Parser::new("1 == 1").parse().unwrap()), Ok(Value::Boolean(true)));
+    assert_eq!(eval(&Parser::new("1 == 2").parse().unwrap()), Ok(Value::Boolean(false)));
+    assert_eq!(eval(&Parser::new("true == 1").parse().unwrap()), Ok(Value::Boolean(false)));
+    assert_eq!(eval(&Parser::new("2 == \"2\"").parse().unwrap()), Ok(Value::Boolean(false)));
+    assert_eq!(eval(&Parser::new("nil == false").parse().unwrap()), Ok(Value::Boolean(false)));
+    assert_eq!(eval(&Parser::new("\"x\" != \"y\"").parse().unwrap()), Ok(Value::Boolean(true)));
+    assert_eq!(eval(&Parser::new("\"x\" == \"x\"").parse().unwrap()), Ok(Value::Boolean(true)));
+    assert_eq!(eval(&Parser::new("3 <? 5 == 3").parse().unwrap()), Ok(Value::Boolean(true)));
+    assert_eq!(eval(&Parser::new("3 >? 5 == 5").parse().unwrap()), Ok(Value::Boolean(true)));
+    assert_eq!(eval(&Parser::new("(1 < 2) == true").parse().unwrap()), Ok(Value::Boolean(true)));
+    assert_eq!(eval(&Parser::new("(3 > 5) != false").parse().unwrap()), Ok(Value::Boolean(false)));
+
+    assert!(!is_equal(&Value::Number(f64::NAN), &Value::Number(f64::NAN)));
+}
+
+#[test]
+fn test_runtime_error() {
+    use crate::parser::Parser;
+
+    let err = eval(&Parser::new("-\"hello\"").parse().unwrap()).unwrap_err();
+    assert!(err.message.starts_with("Tried to invert sign of a non-numeric value"));
+
+    // errors propagate out of nested expressions
+    assert!(eval(&Parser::new("1 + (2 * -nil)").parse().unwrap()).is_err());
+    assert!(eval(&Parser::new("\"x ${1 + true}\"").parse().unwrap()).is_err());
+    assert_eq!(eval(&Parser::new("-(3)").parse().unwrap()), Ok(Value::Number(-3.0)));
+}
+
+#[test]
+fn test_display_value() {
+    assert_eq!(Value::Number(3.0).to_string(), "3");
+    assert_eq!(Value::Number(3.5).to_string(), "3.5");
+    assert_eq!(Value::Number(-0.0).to_string(), "-0");
+    assert_eq!(Value::Boolean(true).to_string(), "true");
+    assert_eq!(Value::Nil.to_string(), "nil");
+    assert_eq!(Value::String("no \"quotes\"".to_string()).to_string(), "no \"quotes\"");
+    assert_eq!(format!("[{}]", Value::Number(42.0)), "[42]");
+}
@@ -1,7 +1,13 @@
 use std::collections::HashMap;
+use std::env;
 use std::fmt;
+use std::io;
+use std::io::IsTerminal;
+use std::mem;
+use std::ops::Range;
+use std::str;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum TokenType {
     // single character tokens
     LeftParen,
@@ -15,6 +21,7 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
 
     // one or two character tokens
     Bang,
@@ -25,12 +32,18 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    GreaterQuestion,
+    LessQuestion,
 
     // tokens that hold a value
     Identifier,
     String,
+    Interpolation,
     Number,
 
+    // only produced when newline tokens are enabled in the scanner
+    Newline,
+
     // keywords
     And,
     Class,
@@ -52,14 +65,61 @@ pub enum TokenType {
     Eof,
 }
 
-#[derive(Debug, PartialEq)]
+// operators and punctuation, with two-character operators first so the
+// scanner always matches the longest operator; adding an operator only
+// requires an entry here (and its token type)
+const OPERATORS: &[(&str, TokenType)] = &[
+    ("!=", TokenType::BangEqual),
+    ("==", TokenType::EqualEqual),
+    (">=", TokenType::GreaterEqual),
+    ("<=", TokenType::LessEqual),
+    (">?", TokenType::GreaterQuestion),
+    ("<?", TokenType::LessQuestion),
+    ("(", TokenType::LeftParen),
+    (")", TokenType::RightParen),
+    ("{", TokenType::LeftBrace),
+    ("}", TokenType::RightBrace),
+    (",", TokenType::Comma),
+    (".", TokenType::Dot),
+    ("-", TokenType::Minus),
+    ("+", TokenType::Plus),
+    (";", TokenType::Semicolon),
+    ("/", TokenType::Slash),
+    ("*", TokenType::Star),
+    ("%", TokenType::Percent),
+    ("!", TokenType::Bang),
+    ("=", TokenType::Equal),
+    (">", TokenType::Greater),
+    ("<", TokenType::Less),
+];
+
+impl TokenType {
+    // the lexeme of operator and punctuation tokens, which is always the same
+    pub fn operator_lexeme(&self) -> Option<&'static str> {
+        OPERATORS.iter()
+            .find(|(_, typ)| typ == self)
+            .map(|(lexeme, _)| *lexeme)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     Number(f64),
     String(String),
+    Interpolation(Vec<StringPart>),
     Identifier(String),
 }
 
-#[derive(Debug, PartialEq)]
+// parts of an interpolated string: literal text, and the source code of
+// the expressions inside ${...} with the line where it starts, to be
+// parsed by the parser
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringPart {
+    Literal(String),
+    Code(String, usize),
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub tok_type: TokenType,
     pub lexeme: String,
@@ -73,16 +133,33 @@ impl Token {
             tok_type: typ,
             lexeme: lexeme.clone(),
             value: None,
-            line: line,
+            line,
+        }
+    }
+
+    // operator token with its canonical lexeme, mostly useful for tests
+    pub fn op(typ: TokenType, line: usize) -> Token {
+        match typ.operator_lexeme() {
+            Some(lexeme) => Token::new(typ, lexeme.to_string(), line),
+            None => panic!("Not an operator token type: {:?}", typ),
         }
     }
 
-    pub fn string_token(s: String, line: usize) -> Token {
+    pub fn string_token(s: String, lex: &str, line: usize) -> Token {
         Token {
             tok_type: TokenType::String,
-            lexeme: s.clone(), // TODO: the lexeme should include quotes
-            value: Some(Value::String(s.clone())),
-            line: line,
+            lexeme: lex.to_string(),
+            value: Some(Value::String(s)),
+            line,
+        }
+    }
+
+    pub fn interpolation(lex: String, parts: Vec<StringPart>, line: usize) -> Token {
+        Token {
+            tok_type: TokenType::Interpolation,
+            lexeme: lex,
+            value: Some(Value::Interpolation(parts)),
+            line,
         }
     }
 
@@ -91,7 +168,7 @@ impl Token {
             tok_type: TokenType::Number,
             lexeme: lex.to_string(),
             value: Some(Value::Number(val)),
-            line: line,
+            line,
         }
     }
 
@@ -100,7 +177,7 @@ impl Token {
             tok_type: TokenType::Identifier,
             lexeme: id.to_string(),
             value: Some(Value::Identifier(id.to_string())),
-            line: line,
+            line,
         }
     }
 
@@ -109,7 +186,7 @@ impl Token {
             tok_type: TokenType::Eof,
             lexeme: String::from(""),
             value: None,
-            line: line,
+            line,
         }
     }
 
@@ -127,31 +204,226 @@ impl fmt::Display for Token {
     }
 }
 
+// error messages are colorized only when stdout is a terminal and
+// the NO_COLOR environment variable is not set to a non-empty value
+pub fn use_color() -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && io::stdout().is_terminal()
+}
+
+pub fn format_error(line: usize, message: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[31mError\x1b[0m in line {}: {}", line, message)
+    } else {
+        format!("Error in line {}: {}", line, message)
+    }
+}
+
+// position of a token in the source: line and column (both starting at 1)
+// where the token starts, and the range of bytes it occupies
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub bytes: Range<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TokenWithSpan {
+    pub token: Token,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ScanError {
+    pub line: usize,
+    pub message: String,
+}
+
+// token counts and other statistics about a source, see scan_stats
+#[derive(Debug, Default)]
+pub struct ScanStats {
+    pub counts: HashMap<TokenType, usize>,
+    pub total: usize,
+    // number of lines in the source, not counting a final newline
+    pub lines: usize,
+    pub errors: Vec<ScanError>,
+}
+
+impl ScanStats {
+    pub fn count(&self, typ: TokenType) -> usize {
+        self.counts.get(&typ).copied().unwrap_or(0)
+    }
+}
+
+// check that source code given as bytes is valid UTF-8; the error
+// reports the byte offset (and line) of the first invalid sequence
+pub fn decode_source(bytes: &[u8]) -> Result<&str, ScanError> {
+    str::from_utf8(bytes).map_err(|e| {
+        let offset = e.valid_up_to();
+        ScanError {
+            line: bytes[..offset].iter().filter(|&&b| b == b'\n').count() + 1,
+            message: format!("Invalid UTF-8 sequence at byte offset {}", offset),
+        }
+    })
+}
+
+// scan the source only to collect statistics; tokens are counted
+// and discarded as they are produced
+pub fn scan_stats(source: &str) -> ScanStats {
+    let mut scanner = Scanner::new(source);
+    scanner.print_errors = false;
+    let mut stats = ScanStats::default();
+
+    while !scanner.is_at_end() {
+        scanner.mark_start();
+        scanner.scan_token();
+        scanner.spans.clear();
+        for tok in scanner.tokens.drain(..) {
+            *stats.counts.entry(tok.tok_type).or_insert(0) += 1;
+            stats.total += 1;
+        }
+    }
+
+    // a newline at the end of the source does not start another line,
+    // and an empty source has no lines at all
+    stats.lines = if source.is_empty() {
+        0
+    } else if source.ends_with('\n') {
+        scanner.line - 1
+    } else {
+        scanner.line
+    };
+    stats.errors = scanner.errors;
+    stats
+}
+
+// scan the whole source without printing anything, returning all tokens
+// with their positions, or all the errors found
+pub fn tokenize(source: &str) -> Result<Vec<TokenWithSpan>, Vec<ScanError>> {
+    let mut scanner = Scanner::new(source);
+    scanner.print_errors = false;
+    scanner.scan_tokens();
+
+    if scanner.had_error {
+        return Err(scanner.errors);
+    }
+
+    let tokens = scanner.tokens
+        .into_iter()
+        .zip(scanner.spans)
+        .map(|(token, span)| TokenWithSpan { token, span })
+        .collect();
+    Ok(tokens)
+}
+
 pub struct Scanner {
     source: String,
+    // for pure ASCII sources the characters are read directly from the bytes
+    // of source, and source_chars is left empty
+    ascii: bool,
     source_chars: Vec<char>,
     start: usize,
     current: usize,
     line: usize,
+    // byte offsets of start and current
+    start_byte: usize,
+    current_byte: usize,
+    // column of the current character and position where the token starts;
+    // a tab moves the column to the next tab stop (every tab_width columns)
+    column: usize,
+    tab_width: usize,
+    start_line: usize,
+    start_column: usize,
     pub tokens: Vec<Token>,
+    pub spans: Vec<Span>,   // span of each token in tokens
     pub had_error: bool,
+    pub errors: Vec<ScanError>,
+    print_errors: bool,
+    emit_newlines: bool,
+    case_insensitive: bool,
+    max_number: Option<f64>,
+    color: bool,
     reserved_words: HashMap<String, TokenType>,
 }
 
 impl Scanner {
     pub fn new(source: &str) -> Scanner {
+        let ascii = source.is_ascii();
         Scanner {
             source: source.to_string(),
-            source_chars: source.chars().collect(),
+            ascii,
+            source_chars: if ascii { vec![] } else { source.chars().collect() },
             start: 0,
             current: 0,
             line: 1,
+            start_byte: 0,
+            current_byte: 0,
+            column: 1,
+            tab_width: 1,
+            start_line: 1,
+            start_column: 1,
             tokens: vec![],
+            spans: vec![],
             had_error: false,
+            errors: vec![],
+            print_errors: true,
+            emit_newlines: false,
+            case_insensitive: false,
+            max_number: None,
+            color: use_color(),
             reserved_words: Scanner::build_reserved_word_map(),
         }
     }
 
+    // scan a source and return only the types of its tokens (including EOF)
+    pub fn token_types(source: &str) -> Vec<TokenType> {
+        let mut scanner = Scanner::new(source);
+        scanner.print_errors = false;
+        scanner.scan_tokens();
+        scanner.tokens.iter().map(|tok| tok.tok_type).collect()
+    }
+
+    // in case-insensitive mode keywords are recognized in any case and
+    // identifiers are lowercased, so Print, PRINT and print are the same
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+    }
+
+    // reject number literals greater than max_number (no limit by default)
+    pub fn set_max_number(&mut self, max_number: Option<f64>) {
+        self.max_number = max_number;
+    }
+
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+    }
+
+    // emit a Newline token for each line break outside of strings and
+    // comments, for experiments with whitespace-sensitive syntax
+    pub fn set_emit_newlines(&mut self, emit_newlines: bool) {
+        self.emit_newlines = emit_newlines;
+    }
+
+    // scanner for a fragment of the source of this scanner (the code of an
+    // interpolation), with the same settings and starting at the given line;
+    // it doesn't print errors, as they are reported when scanning the string
+    pub fn fragment(&self, source: &str, line: usize) -> Scanner {
+        let mut scanner = Scanner::new(source);
+        scanner.line = line;
+        scanner.start_line = line;
+        scanner.tab_width = self.tab_width;
+        scanner.print_errors = false;
+        scanner.case_insensitive = self.case_insensitive;
+        scanner.max_number = self.max_number;
+        scanner.color = self.color;
+        scanner
+    }
+
     fn build_reserved_word_map() -> HashMap<String, TokenType> {
         HashMap::from([
             ("and".to_string(), TokenType::And),
@@ -173,17 +445,83 @@ impl Scanner {
         ])
     }
 
+    // current and start count characters, so the end of the source is at
+    // its length in characters (the same as in bytes only for ASCII)
+    fn source_len(&self) -> usize {
+        if self.ascii {
+            self.source.len()
+        } else {
+            self.source_chars.len()
+        }
+    }
+
     fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+        self.current >= self.source_len()
     }
 
     pub fn scan_tokens(&mut self) {
         while !self.is_at_end() {
-            self.start = self.current;
+            self.mark_start();
             self.scan_token();
         }
 
-        self.tokens.push(Token::eof(self.line));
+        self.mark_start();
+        self.push_token(Token::eof(self.line));
+    }
+
+    fn mark_start(&mut self) {
+        self.start = self.current;
+        self.start_byte = self.current_byte;
+        self.start_line = self.line;
+        self.start_column = self.column;
+    }
+
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.column = 1;
+    }
+
+    fn push_token(&mut self, token: Token) {
+        self.spans.push(Span {
+            line: self.start_line,
+            column: self.start_column,
+            bytes: self.start_byte..self.current_byte,
+        });
+        self.tokens.push(token);
+    }
+
+    // add more source code at the end of the input and scan it, keeping the
+    // tokens already produced (the previous EOF token is replaced); if
+    // rescan_last is set and the last token reaches the end of the previous
+    // source, it is scanned again, as it may continue in the new source
+    // (e.g. 12 followed by 3 is the number 123)
+    pub fn append(&mut self, source: &str, rescan_last: bool) {
+        if self.tokens.last().is_some_and(|tok| tok.is_eof()) {
+            self.tokens.pop();
+            self.spans.pop();
+        }
+        if rescan_last && self.spans.last().is_some_and(|span| span.bytes.end == self.source.len()) {
+            let span = self.spans.pop().expect("there should be a span");
+            self.tokens.pop();
+            self.current = if self.ascii {
+                span.bytes.start
+            } else {
+                self.source[..span.bytes.start].chars().count()
+            };
+            self.current_byte = span.bytes.start;
+            self.line = span.line;
+            self.column = span.column;
+        }
+        if self.ascii && !source.is_ascii() {
+            // switch to the char-based path
+            self.ascii = false;
+            self.source_chars = self.source.chars().collect();
+        }
+        self.source.push_str(source);
+        if !self.ascii {
+            self.source_chars.extend(source.chars());
+        }
+        self.scan_tokens();
     }
 
     fn scan_token(&mut self) {
@@ -191,32 +529,33 @@ impl Scanner {
         //let c = self.advance();
 
         match self.advance() {
-            '(' => self.add_token(TokenType::LeftParen),
-            ')' => self.add_token(TokenType::RightParen),
-            '{' => self.add_token(TokenType::LeftBrace),
-            '}' => self.add_token(TokenType::RightBrace),
-            ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
-            '+' => self.add_token(TokenType::Plus),
-            ';' => self.add_token(TokenType::Semicolon),
             '/' => self.comment_or_slash(),
-            '*' => self.add_token(TokenType::Star),
-            '!' => self.add_alternatives('=', TokenType::BangEqual, TokenType::Bang),
-            '=' => self.add_alternatives('=', TokenType::EqualEqual, TokenType::Equal),
-            '>' => self.add_alternatives('=', TokenType::GreaterEqual, TokenType::Greater),
-            '<' => self.add_alternatives('=', TokenType::LessEqual, TokenType::Less),
             '"' => self.string(),
-            c if c.is_digit(10) => self.number(),
+            c if c.is_ascii_digit() => self.number(),
             c if c.is_whitespace() => self.process_whitespace(c),
-            c if c.is_alphabetic() => self.identifier(),
-            c => self.error(format!("Unrecognized character: {}", c)),
+            'r' if self.peek() == Some('"') => self.raw_string(),
+            c if c.is_alphabetic() || c == '_' => self.identifier(),
+            c => self.operator(c),
+        }
+    }
+
+    fn char_at(&self, i: usize) -> char {
+        if self.ascii {
+            self.source.as_bytes()[i] as char
+        } else {
+            self.source_chars[i]
         }
     }
 
     fn advance(&mut self) -> char {
-        let res = self.source_chars[self.current];
+        let res = self.char_at(self.current);
         self.current += 1;
+        self.current_byte += res.len_utf8();
+        if res == '\t' {
+            self.column = (self.column - 1) / self.tab_width * self.tab_width + self.tab_width + 1;
+        } else {
+            self.column += 1;
+        }
         res
     }
 
@@ -224,38 +563,66 @@ impl Scanner {
         if self.is_at_end() {
             None
         } else {
-            Some(self.source_chars[self.current])
+            Some(self.char_at(self.current))
         }
     }
 
     fn add_token(&mut self, typ: TokenType) {
         let lexeme = String::from(
             self.source
-                .get(self.start..self.current)
+                .get(self.start_byte..self.current_byte)
                 .expect("this should never happen 2"),
         );
-        self.tokens.push(Token::new(typ, lexeme, self.line));
+        self.push_token(Token::new(typ, lexeme, self.line));
     }
 
     fn error(&mut self, message: String) {
-        println!("Error in line {}: {}", self.line, message);
+        self.error_at(self.line, message);
+    }
+
+    fn error_at(&mut self, line: usize, message: String) {
+        if self.print_errors {
+            println!("{}", format_error(line, &message, self.color));
+        }
         self.had_error = true;
+        self.errors.push(ScanError { line, message });
     }
 
     fn match_next(&mut self, c: char) -> bool {
-        if self.is_at_end() {
-            false
-        } else if self.source_chars[self.current] != c {
+        if self.is_at_end() || self.char_at(self.current) != c {
             false
         } else {
-            self.current += 1;
+            self.advance();
             true
         }
     }
 
-    fn add_alternatives(&mut self, next: char, typ_match: TokenType, typ_not_match: TokenType) {
-        let does_match = self.match_next(next);
-        self.add_token(if does_match { typ_match } else { typ_not_match });
+    // scan an operator starting with c (already consumed) using the table
+    // of operators; the first entry that matches is the longest operator
+    fn operator(&mut self, c: char) {
+        for (lexeme, typ) in OPERATORS {
+            let mut chars = lexeme.chars();
+            if chars.next() != Some(c) {
+                continue;
+            }
+
+            let rest = chars.as_str();
+            if self.next_chars_are(rest) {
+                for _ in rest.chars() {
+                    self.advance();
+                }
+                self.add_token(*typ);
+                return;
+            }
+        }
+
+        self.error(format!("Unrecognized character: {}", c));
+    }
+
+    fn next_chars_are(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| {
+            self.current + i < self.source_len() && self.char_at(self.current + i) == c
+        })
     }
 
     fn comment_or_slash(&mut self) {
@@ -268,26 +635,89 @@ impl Scanner {
                     self.advance();
                 }
             }
+        } else if self.match_next('*') {
+            self.block_comment();
         } else {
             self.add_token(TokenType::Slash);
         }
     }
 
+    // block comments /* ... */ can be nested, so each /* inside the comment
+    // needs a matching */
+    fn block_comment(&mut self) {
+        let mut depth = 1;
+        while let Some(c) = self.peek() {
+            self.advance();
+            match c {
+                '\n' => self.new_line(),
+                '*' if self.match_next('/') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                },
+                '/' if self.match_next('*') => depth += 1,
+                _ => (),
+            }
+        }
+        self.error("Unterminated block comment".to_string());
+    }
+
     fn process_whitespace(&mut self, c: char) {
         if c == '\n' {
-            self.line += 1;
+            if self.emit_newlines {
+                self.add_token(TokenType::Newline);
+            }
+            self.new_line();
         }
     }
 
     fn string(&mut self) {
+        // parts of an interpolated string, only used if there is a ${ in it
+        let mut parts = vec![];
+        let mut literal = String::new();
+
         while let Some(c) = self.peek() {
             if c == '"' {
                 break;
-            } else {
-                if c == '\n' {
-                    self.line += 1;
+            }
+
+            self.advance();
+            if c == '\n' {
+                self.new_line();
+            }
+
+            if c == '\\' {
+                if let Some(escaped) = self.peek() {
+                    self.advance();
+                    if escaped == '\n' {
+                        self.new_line();
+                    }
+                    if let Some(decoded) = self.escape(escaped) {
+                        literal.push(decoded);
+                    }
                 }
+            } else if c == '$' && self.peek() == Some('{') {
                 self.advance();
+                if !literal.is_empty() {
+                    parts.push(StringPart::Literal(mem::take(&mut literal)));
+                }
+                let line = self.line;
+                let mut code = String::new();
+                if !self.interpolated_code(&mut code) {
+                    self.error("Unterminated interpolation in string literal".to_string());
+                    return;
+                }
+                // scan the code now to report its lexical errors with the
+                // others; the parser scans it again to parse it
+                let mut fragment = self.fragment(&code, line);
+                fragment.scan_tokens();
+                for e in fragment.errors {
+                    self.error_at(e.line, e.message);
+                }
+                parts.push(StringPart::Code(code, line));
+            } else {
+                literal.push(c);
             }
         }
 
@@ -298,17 +728,131 @@ impl Scanner {
 
         self.advance(); // consume the closing double quote
 
+        // the lexeme is the source text, quotes included, with escape
+        // sequences as written
+        let lexeme = self.current_lexeme();
+        if parts.is_empty() {
+            self.push_token(Token::string_token(literal, &lexeme, self.line));
+        } else {
+            if !literal.is_empty() {
+                parts.push(StringPart::Literal(literal));
+            }
+            self.push_token(Token::interpolation(lexeme, parts, self.line));
+        }
+    }
+
+    // the character represented by an escape sequence (c is the character
+    // after the backslash), or None if the escape is not valid
+    fn escape(&mut self, c: char) -> Option<char> {
+        match c {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '"' => Some('"'),
+            '\\' => Some('\\'),
+            '$' => Some('$'),   // so that a string can have a literal ${
+            _ => {
+                self.error(format!("Unknown escape sequence in string literal: \\{}", c));
+                None
+            },
+        }
+    }
+
+    // raw strings (r"...") are taken literally, without interpolation
+    // or any other processing of their contents
+    fn raw_string(&mut self) {
+        self.advance(); // consume the opening double quote
+
+        while let Some(c) = self.peek() {
+            if c == '"' {
+                break;
+            }
+            self.advance();
+            if c == '\n' {
+                self.new_line();
+            }
+        }
+
+        if self.is_at_end() {
+            self.error("Unterminated raw string literal".to_string());
+            return;
+        }
+
+        self.advance(); // consume the closing double quote
+
         let value = String::from(
             self.source
-                .get(self.start + 1..self.current - 1)
-                .expect("this should never happen 3"),
+                .get(self.start_byte + 2..self.current_byte - 1)
+                .expect("there should be a string in this range"),
         );
-        self.tokens.push(Token::string_token(value, self.line));
+        let lexeme = self.current_lexeme();
+        self.push_token(Token::string_token(value, &lexeme, self.line));
+    }
+
+    // copy the source of an interpolated expression into code, consuming
+    // the closing brace; nested braces and strings are copied as they are
+    fn interpolated_code(&mut self, code: &mut String) -> bool {
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            self.advance();
+            if c == '\n' {
+                self.new_line();
+            }
+
+            match c {
+                '}' if depth == 0 => return true,
+                '}' => depth -= 1,
+                '{' => depth += 1,
+                _ => ()
+            }
+            code.push(c);
+
+            if c == '"' && !self.nested_string(code) {
+                return false;
+            }
+        }
+        false
+    }
+
+    // copy a string inside an interpolated expression, up to and including
+    // its closing quote; it may have interpolations of its own
+    fn nested_string(&mut self, code: &mut String) -> bool {
+        while let Some(c) = self.peek() {
+            self.advance();
+            if c == '\n' {
+                self.new_line();
+            }
+            code.push(c);
+
+            if c == '"' {
+                return true;
+            }
+            if c == '\\' {
+                // copy an escaped character as it is, it may be a quote
+                if let Some(escaped) = self.peek() {
+                    self.advance();
+                    if escaped == '\n' {
+                        self.new_line();
+                    }
+                    code.push(escaped);
+                }
+                continue;
+            }
+            if c == '$' && self.peek() == Some('{') {
+                self.advance();
+                code.push('{');
+                if !self.interpolated_code(code) {
+                    return false;
+                }
+                code.push('}');
+            }
+        }
+        false
     }
 
     fn advance_digits(&mut self) {
         while let Some(c) = self.peek() {
-            if !c.is_digit(10) {
+            if !c.is_ascii_digit() {
                 break;
             }
             self.advance();
@@ -318,7 +862,7 @@ impl Scanner {
     fn current_lexeme(&self) -> String {
         String::from(
             self.source
-                .get(self.start..self.current)
+                .get(self.start_byte..self.current_byte)
                 .expect("there should be a string in this range"),
         )
     }
@@ -341,36 +885,42 @@ impl Scanner {
         let str_value = self.current_lexeme();
         let val: f64 = str_value.parse().unwrap();
 
-        self.tokens
-            .push(Token::number_token(val, &str_value, self.line));
+        if let Some(max) = self.max_number {
+            if val > max {
+                self.error(format!("Number literal {} is larger than the maximum allowed ({})",
+                                   str_value, max));
+                return;
+            }
+        }
+
+        self.push_token(Token::number_token(val, &str_value, self.line));
     }
 
+    // check the character after the one at current (the dot)
     fn peek_next_is_digit(&self) -> bool {
-        if self.current + 2 >= self.source.len() {
-            false
-        } else {
-            let c = self.source_chars[self.current + 2];
-            if c.is_digit(10) {
-                true
-            } else {
-                false
-            }
-        }
+        self.current + 1 < self.source_len() && self.char_at(self.current + 1).is_ascii_digit()
     }
 
     fn identifier(&mut self) {
         while let Some(c) = self.peek() {
-            if !c.is_alphabetic() {
+            if !c.is_alphanumeric() && c != '_' {
                 break;
             }
             self.advance();
         }
 
-        let ident = self.current_lexeme();
+        // in case-insensitive mode only the lookup key and the identifier
+        // value are lowercased, the lexeme keeps the source text
+        let lexeme = self.current_lexeme();
+        let key = if self.case_insensitive { lexeme.to_lowercase() } else { lexeme.clone() };
 
         // check if it is a reserved word
-        match self.reserved_words.get(&ident) {
-            None => self.tokens.push(Token::identifier(&ident, self.line)),
+        match self.reserved_words.get(&key) {
+            None => {
+                let mut tok = Token::identifier(&key, self.line);
+                tok.lexeme = lexeme;
+                self.push_token(tok);
+            },
             Some(&toktyp) => self.add_token(toktyp),
         }
     }
@@ -378,13 +928,229 @@ impl Scanner {
 
 // tests
 #[test]
-fn test_operators() {
-    let mut scanner = Scanner::new("(/*){ ;+\t -}!({.,.!=<>====!})");
+fn test_error_format() {
+    let plain = format_error(3, "Unrecognized character: #", false);
+    assert_eq!(plain, "Error in line 3: Unrecognized character: #");
+    assert!(!plain.contains('\x1b'));
+
+    let colored = format_error(3, "Unrecognized character: #", true);
+    assert!(colored.contains("\x1b[31mError\x1b[0m"));
+}
 
+#[test]
+fn test_scan_stats() {
+    let stats = scan_stats("var x = 1 + 2;\nvar y = x * x;\n# print y;");
+
+    assert_eq!(stats.count(TokenType::Var), 2);
+    assert_eq!(stats.count(TokenType::Identifier), 5);
+    assert_eq!(stats.count(TokenType::Equal), 2);
+    assert_eq!(stats.count(TokenType::Number), 2);
+    assert_eq!(stats.count(TokenType::Plus), 1);
+    assert_eq!(stats.count(TokenType::Star), 1);
+    assert_eq!(stats.count(TokenType::Print), 1);
+    assert_eq!(stats.count(TokenType::Semicolon), 3);
+    assert_eq!(stats.count(TokenType::Eof), 0);
+    assert_eq!(stats.total, 17);
+    assert_eq!(stats.lines, 3);
+    assert_eq!(
+        stats.errors,
+        vec![ScanError {
+            line: 3,
+            message: "Unrecognized character: #".to_string()
+        }]
+    );
+
+    assert_eq!(scan_stats("1;\n2;\n").lines, 2);
+    assert_eq!(scan_stats("1;\n2;").lines, 2);
+    assert_eq!(scan_stats("\n").lines, 1);
+    assert_eq!(scan_stats("").lines, 0);
+}
+
+#[test]
+fn test_ascii_path() {
+    let source = "var x = (12.5 + y) * 3; // comment\nprint \"str\" != x <= 4;";
+
+    let mut ascii_scanner = Scanner::new(source);
+    assert!(ascii_scanner.ascii);
+    ascii_scanner.scan_tokens();
+
+    // force the char-based path for the same source
+    let mut char_scanner = Scanner::new(source);
+    char_scanner.ascii = false;
+    char_scanner.source_chars = source.chars().collect();
+    char_scanner.scan_tokens();
+
+    assert!(!ascii_scanner.had_error);
+    assert!(!char_scanner.had_error);
+    assert_eq!(ascii_scanner.tokens, char_scanner.tokens);
+}
+
+// timing of the ASCII path against the char-based path on a large source;
+// run with: cargo test --release bench_ascii_path -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_ascii_path() {
+    use std::time::Instant;
+
+    let line = "var x = (12.5 + y) * 3 >? z; // comment\nprint \"str ${x}\" != x <= 4;\n";
+    let source = line.repeat(20_000);
+    let runs = 10;
+
+    let time = |ascii: bool| {
+        let start = Instant::now();
+        let mut count = 0;
+        for _ in 0..runs {
+            let mut scanner = Scanner::new(&source);
+            if !ascii {
+                scanner.ascii = false;
+                scanner.source_chars = source.chars().collect();
+            }
+            scanner.scan_tokens();
+            count += scanner.tokens.len();
+        }
+        (start.elapsed() / runs, count)
+    };
+
+    let (ascii_time, ascii_count) = time(true);
+    let (char_time, char_count) = time(false);
+    assert_eq!(ascii_count, char_count);
+    println!("{} bytes, {} tokens", source.len(), ascii_count / runs as usize);
+    println!("ascii path: {:?} per scan", ascii_time);
+    println!("char path:  {:?} per scan", char_time);
+}
+
+#[test]
+fn test_tokenize() {
+    let tokens = tokenize("var x = 10;\n  print x <= 3;").expect("no errors expected");
+
+    let expected = vec![
+        (TokenType::Var, 1, 1, 0..3),
+        (TokenType::Identifier, 1, 5, 4..5),
+        (TokenType::Equal, 1, 7, 6..7),
+        (TokenType::Number, 1, 9, 8..10),
+        (TokenType::Semicolon, 1, 11, 10..11),
+        (TokenType::Print, 2, 3, 14..19),
+        (TokenType::Identifier, 2, 9, 20..21),
+        (TokenType::LessEqual, 2, 11, 22..24),
+        (TokenType::Number, 2, 14, 25..26),
+        (TokenType::Semicolon, 2, 15, 26..27),
+        (TokenType::Eof, 2, 16, 27..27),
+    ];
+
+    assert_eq!(tokens.len(), expected.len());
+    for (tok, (typ, line, column, bytes)) in tokens.iter().zip(expected) {
+        assert_eq!(tok.token.tok_type, typ);
+        assert_eq!(tok.span, Span { line, column, bytes });
+    }
+}
+
+#[test]
+fn test_tab_width() {
+    let source = "\tvar x;\n\t\tx = 1;";
+
+    let mut scanner = Scanner::new(source);
     scanner.scan_tokens();
+    let columns: Vec<usize> = scanner.spans.iter().map(|span| span.column).collect();
+    assert_eq!(columns, vec![2, 6, 7, 3, 5, 7, 8, 9]);
 
-    assert!(!scanner.had_error);
+    let mut scanner = Scanner::new(source);
+    scanner.set_tab_width(4);
+    scanner.scan_tokens();
+    let columns: Vec<usize> = scanner.spans.iter().map(|span| span.column).collect();
+    assert_eq!(columns, vec![5, 9, 10, 9, 11, 13, 14, 15]);
+
+    // a tab after other text moves to the next tab stop
+    let mut scanner = Scanner::new("a\tb\nabcd\tc\nabcde\t\td");
+    scanner.set_tab_width(4);
+    scanner.scan_tokens();
+    let columns: Vec<usize> = scanner.spans.iter().map(|span| span.column).collect();
+    assert_eq!(columns, vec![1, 5, 1, 9, 1, 13, 14]);
+}
+
+#[test]
+fn test_tokenize_errors() {
+    let errors = tokenize("1 # 2\n@").expect_err("errors expected");
+
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].line, 1);
+    assert_eq!(errors[1].line, 2);
+}
+
+#[test]
+fn test_operator_tokens() {
+    let plus = Token::op(TokenType::Plus, 3);
+    assert_eq!(plus.tok_type, TokenType::Plus);
+    assert_eq!(plus.lexeme, "+");
+    assert_eq!(plus.value, None);
+    assert_eq!(plus.line, 3);
+
+    assert_eq!(Token::op(TokenType::BangEqual, 1).lexeme, "!=");
+    assert_eq!(Token::op(TokenType::LessQuestion, 1).lexeme, "<?");
+
+    // tokens built by the scanner are the same
+    let mut scanner = Scanner::new("<= ;");
+    scanner.scan_tokens();
+    assert_eq!(scanner.tokens[0], Token::op(TokenType::LessEqual, 1));
+    assert_eq!(scanner.tokens[1], Token::op(TokenType::Semicolon, 1));
+}
+
+#[test]
+#[should_panic(expected = "Not an operator token type")]
+fn test_operator_token_invalid() {
+    Token::op(TokenType::Identifier, 1);
+}
+
+#[test]
+fn test_operator_table() {
+    for (lexeme, typ) in OPERATORS {
+        if *typ == TokenType::Slash {
+            continue;   // scanned separately because of comments
+        }
+
+        let mut scanner = Scanner::new(lexeme);
+        scanner.scan_tokens();
+
+        assert!(!scanner.had_error);
+        assert_eq!(scanner.tokens.len(), 2);
+        assert_eq!(scanner.tokens[0].tok_type, *typ);
+        assert_eq!(scanner.tokens[0].lexeme, *lexeme);
+    }
+
+    // the longest operator is matched first
+    assert_eq!(Scanner::token_types(">?=<!"),
+               vec![TokenType::GreaterQuestion, TokenType::Equal,
+                    TokenType::Less, TokenType::Bang, TokenType::Eof]);
+}
 
+#[test]
+fn test_decode_source() {
+    let bytes = "print \"olá\";".as_bytes();
+    assert_eq!(decode_source(bytes), Ok("print \"olá\";"));
+
+    let bytes = b"1 + 2;\nprint \"ol\xe1\";";
+    assert_eq!(
+        decode_source(bytes),
+        Err(ScanError {
+            line: 2,
+            message: "Invalid UTF-8 sequence at byte offset 16".to_string()
+        })
+    );
+}
+
+#[test]
+fn test_token_types() {
+    assert_eq!(Scanner::token_types(""), vec![TokenType::Eof]);
+    assert_eq!(Scanner::token_types("var x = \"s\";"),
+               vec![TokenType::Var, TokenType::Identifier, TokenType::Equal,
+                    TokenType::String, TokenType::Semicolon, TokenType::Eof]);
+
+    // characters with errors produce no tokens
+    assert_eq!(Scanner::token_types("1 # 2"),
+               vec![TokenType::Number, TokenType::Number, TokenType::Eof]);
+}
+
+#[test]
+fn test_operators() {
     let types = vec![
         TokenType::LeftParen,
         TokenType::Slash,
@@ -412,11 +1178,57 @@ fn test_operators() {
         TokenType::Eof,
     ];
 
-    let mut typ_it = types.iter();
-    for tok in scanner.tokens {
-        let typ = typ_it.next().expect("q?");
-        assert_eq!(tok.tok_type, *typ);
-    }
+    assert_eq!(Scanner::token_types("(/ *){ ;+\t -}!({.,.!=<>====!})"), types);
+}
+
+#[test]
+fn test_min_max_operators() {
+    let types = vec![
+        TokenType::Number,
+        TokenType::LessQuestion,
+        TokenType::Number,
+        TokenType::GreaterQuestion,
+        TokenType::Number,
+        TokenType::Less,
+        TokenType::Number,
+        TokenType::Greater,
+        TokenType::Number,
+        TokenType::LessEqual,
+        TokenType::GreaterEqual,
+        TokenType::Eof,
+    ];
+
+    assert_eq!(Scanner::token_types("3 <? 5 >? 1 < 2 > 4 <= >="), types);
+}
+
+#[test]
+fn test_percent() {
+    let mut scanner = Scanner::new("7 % 3");
+
+    scanner.scan_tokens();
+
+    assert!(!scanner.had_error);
+    assert_eq!(scanner.tokens[1].tok_type, TokenType::Percent);
+    assert_eq!(scanner.tokens[1].lexeme, "%");
+}
+
+#[test]
+fn test_newline_tokens() {
+    let source = "print 1 // comment\nprint \"a\nb\"\n";
+
+    assert_eq!(Scanner::token_types(source),
+               vec![TokenType::Print, TokenType::Number,
+                    TokenType::Print, TokenType::String, TokenType::Eof]);
+
+    let mut scanner = Scanner::new(source);
+    scanner.set_emit_newlines(true);
+    scanner.scan_tokens();
+    let types: Vec<TokenType> = scanner.tokens.iter().map(|tok| tok.tok_type).collect();
+    assert_eq!(types, vec![TokenType::Print, TokenType::Number, TokenType::Newline,
+                           TokenType::Print, TokenType::String, TokenType::Newline,
+                           TokenType::Eof]);
+    assert_eq!(scanner.tokens[2].line, 1);
+    assert_eq!(scanner.tokens[5].line, 3);
 }
 
 #[test]
@@ -434,13 +1246,103 @@ fn test_string_literal_1() {
         .expect("There should be a string token in the stream");
 
     assert_eq!(str_tok.tok_type, TokenType::String);
-    assert_eq!(str_tok.lexeme, "abscondmal");
+    assert_eq!(str_tok.lexeme, "\"abscondmal\"");
     assert_eq!(
         str_tok.value,
         Some(Value::String("abscondmal".to_string()))
     );
 }
 
+#[test]
+fn test_non_ascii_source() {
+    // string literals and identifiers with multi-byte characters
+    let mut scanner = Scanner::new("\"café\" + naïve == \"ü\"");
+
+    scanner.scan_tokens();
+
+    assert!(!scanner.had_error);
+    assert_eq!(scanner.tokens.len(), 6);
+    assert_eq!(scanner.tokens[0].tok_type, TokenType::String);
+    assert_eq!(scanner.tokens[0].value, Some(Value::String("café".to_string())));
+    assert_eq!(scanner.tokens[1].tok_type, TokenType::Plus);
+    assert_eq!(scanner.tokens[2].tok_type, TokenType::Identifier);
+    assert_eq!(scanner.tokens[2].lexeme, "naïve");
+    assert_eq!(scanner.tokens[3].tok_type, TokenType::EqualEqual);
+    assert_eq!(scanner.tokens[4].value, Some(Value::String("ü".to_string())));
+    assert!(scanner.tokens[5].is_eof());
+
+    // the source ends right after a multi-byte character
+    assert_eq!(Scanner::token_types("\"é\""), vec![TokenType::String, TokenType::Eof]);
+}
+
+#[test]
+fn test_raw_string() {
+    let mut scanner = Scanner::new("r\"\\n\" r\"C:\\path\\${x}\" r + 1");
+
+    scanner.scan_tokens();
+
+    assert!(!scanner.had_error);
+    assert_eq!(scanner.tokens[0].tok_type, TokenType::String);
+    assert_eq!(scanner.tokens[0].value, Some(Value::String("\\n".to_string())));
+    assert_eq!(scanner.tokens[0].lexeme, "r\"\\n\"");
+    assert_eq!(scanner.tokens[1].tok_type, TokenType::String);
+    assert_eq!(scanner.tokens[1].value, Some(Value::String("C:\\path\\${x}".to_string())));
+    assert_eq!(scanner.tokens[2].tok_type, TokenType::Identifier);
+
+    let mut scanner = Scanner::new("r\"unterminated");
+
+    scanner.scan_tokens();
+
+    assert!(scanner.had_error);
+    assert_eq!(scanner.errors[0].message, "Unterminated raw string literal");
+}
+
+#[test]
+fn test_interpolation() {
+    let mut scanner = Scanner::new("\"hello ${name}, you have ${count + 1} items\"");
+
+    scanner.scan_tokens();
+
+    assert!(!scanner.had_error);
+    assert_eq!(scanner.tokens[0].tok_type, TokenType::Interpolation);
+    assert_eq!(scanner.tokens[0].lexeme, "\"hello ${name}, you have ${count + 1} items\"");
+    assert_eq!(
+        scanner.tokens[0].value,
+        Some(Value::Interpolation(vec![
+            StringPart::Literal("hello ".to_string()),
+            StringPart::Code("name".to_string(), 1),
+            StringPart::Literal(", you have ".to_string()),
+            StringPart::Code("count + 1".to_string(), 1),
+            StringPart::Literal(" items".to_string()),
+        ]))
+    );
+
+    // braces and strings (with interpolations) inside the expression
+    let mut scanner = Scanner::new("\"${ {} } ${\"}${x}\"}\"");
+
+    scanner.scan_tokens();
+
+    assert!(!scanner.had_error);
+    assert_eq!(
+        scanner.tokens[0].value,
+        Some(Value::Interpolation(vec![
+            StringPart::Code(" {} ".to_string(), 1),
+            StringPart::Literal(" ".to_string()),
+            StringPart::Code("\"}${x}\"".to_string(), 1),
+        ]))
+    );
+}
+
+#[test]
+fn test_unterminated_interpolation() {
+    let mut scanner = Scanner::new("\"value: ${1 + 2\"");
+
+    scanner.scan_tokens();
+
+    assert!(scanner.had_error);
+    assert_eq!(scanner.errors[0].message, "Unterminated interpolation in string literal");
+}
+
 #[test]
 fn test_number_literal_1() {
     let mut scanner = Scanner::new("1234 + 37.52");
@@ -474,6 +1376,49 @@ fn test_number_literal_1() {
     assert_eq!(num_tok_2.value, Some(Value::Number(37.52)));
 }
 
+#[test]
+#[allow(clippy::approx_constant)]
+fn test_number_literal_2() {
+    let mut scanner = Scanner::new("3.14");
+    scanner.scan_tokens();
+    assert_eq!(scanner.tokens[0].lexeme, "3.14");
+    assert_eq!(scanner.tokens[0].value, Some(Value::Number(3.14)));
+
+    let mut scanner = Scanner::new("12.5 + 1");
+    scanner.scan_tokens();
+    assert_eq!(scanner.tokens[0].value, Some(Value::Number(12.5)));
+    assert_eq!(scanner.tokens[1].tok_type, TokenType::Plus);
+    assert_eq!(scanner.tokens[2].value, Some(Value::Number(1.0)));
+
+    // the dot is not part of the number if no digits follow it
+    let mut scanner = Scanner::new("4.");
+    scanner.scan_tokens();
+    assert_eq!(scanner.tokens[0].lexeme, "4");
+    assert_eq!(scanner.tokens[0].value, Some(Value::Number(4.0)));
+    assert_eq!(scanner.tokens[1].tok_type, TokenType::Dot);
+    assert!(scanner.tokens[2].is_eof());
+}
+
+#[test]
+fn test_max_number() {
+    let mut scanner = Scanner::new("999999999 + 1000000000");
+    scanner.set_max_number(Some(1e9));
+    scanner.scan_tokens();
+
+    assert!(!scanner.had_error);
+    assert_eq!(scanner.tokens[2].value, Some(Value::Number(1e9)));
+
+    let mut scanner = Scanner::new("1 + 1000000001");
+    scanner.set_max_number(Some(1e9));
+    scanner.scan_tokens();
+
+    assert!(scanner.had_error);
+    assert_eq!(scanner.errors[0].message,
+               "Number literal 1000000001 is larger than the maximum allowed (1000000000)");
+    assert_eq!(Scanner::token_types("1 + 1000000001"),
+               vec![TokenType::Number, TokenType::Plus, TokenType::Number, TokenType::Eof]);
+}
+
 #[test]
 fn test_keywords_1() {
     let mut scanner = Scanner::new("class for lunch");
@@ -509,12 +1454,6 @@ fn test_keywords_1() {
 
 #[test]
 fn test_keywords_2() {
-    let mut scanner = Scanner::new("and for if while class return else false print true");
-
-    scanner.scan_tokens();
-
-    assert!(!scanner.had_error);
-
     let types = vec![
         TokenType::And,
         TokenType::For,
@@ -529,11 +1468,35 @@ fn test_keywords_2() {
         TokenType::Eof,
     ];
 
-    let mut typ_it = types.iter();
-    for tok in scanner.tokens {
-        let typ = typ_it.next().expect("A token was expected");
-        assert_eq!(tok.tok_type, *typ);
-    }
+    assert_eq!(Scanner::token_types("and for if while class return else false print true"), types);
+}
+
+#[test]
+fn test_case_insensitive() {
+    let source = "VAR X = 1; Print x;";
+
+    let mut scanner = Scanner::new(source);
+    scanner.set_case_insensitive(true);
+    scanner.scan_tokens();
+
+    let types: Vec<TokenType> = scanner.tokens.iter().map(|tok| tok.tok_type).collect();
+    assert_eq!(types, vec![TokenType::Var, TokenType::Identifier, TokenType::Equal,
+                           TokenType::Number, TokenType::Semicolon, TokenType::Print,
+                           TokenType::Identifier, TokenType::Semicolon, TokenType::Eof]);
+    assert_eq!(scanner.tokens[1].value, scanner.tokens[6].value);
+
+    // lexemes are the text in the source, only the values are lowercased
+    let lexemes: Vec<&str> = scanner.tokens.iter().map(|tok| tok.lexeme.as_str()).collect();
+    assert_eq!(lexemes, vec!["VAR", "X", "=", "1", ";", "Print", "x", ";", ""]);
+    assert_eq!(scanner.tokens[1].value, Some(Value::Identifier("x".to_string())));
+
+    // by default keywords are lowercase and identifiers keep their case
+    let mut scanner = Scanner::new(source);
+    scanner.scan_tokens();
+
+    assert_eq!(scanner.tokens[0].tok_type, TokenType::Identifier);
+    assert_eq!(scanner.tokens[5].tok_type, TokenType::Identifier);
+    assert_ne!(scanner.tokens[1].value, scanner.tokens[6].value);
 }
 
 #[test]
@@ -580,3 +1543,104 @@ fn test_identifiers_1() {
     assert_eq!(num_tok_1.lexeme, "37");
     assert_eq!(num_tok_1.value, Some(Value::Number(37.0)));
 }
+
+#[test]
+fn test_identifiers_2() {
+    let mut scanner = Scanner::new("var x1 = y_2; _tmp r2d2 print_");
+    scanner.scan_tokens();
+
+    assert!(!scanner.had_error);
+    let expected = vec![
+        (TokenType::Var, "var"),
+        (TokenType::Identifier, "x1"),
+        (TokenType::Equal, "="),
+        (TokenType::Identifier, "y_2"),
+        (TokenType::Semicolon, ";"),
+        (TokenType::Identifier, "_tmp"),
+        (TokenType::Identifier, "r2d2"),
+        (TokenType::Identifier, "print_"),
+    ];
+    for (tok, (typ, lexeme)) in scanner.tokens.iter().zip(expected) {
+        assert_eq!(tok.tok_type, typ);
+        assert_eq!(tok.lexeme, lexeme);
+    }
+    assert_eq!(scanner.tokens[1].value, Some(Value::Identifier("x1".to_string())));
+    assert_eq!(scanner.tokens.len(), 9);
+}
+
+#[test]
+fn test_block_comments() {
+    assert_eq!(Scanner::token_types("1 /* a comment */ + 2"),
+               vec![TokenType::Number, TokenType::Plus, TokenType::Number, TokenType::Eof]);
+    assert_eq!(Scanner::token_types("4 /**/ / 2"),
+               vec![TokenType::Number, TokenType::Slash, TokenType::Number, TokenType::Eof]);
+
+    // lines are counted inside the comment
+    let mut scanner = Scanner::new("1\n/* first\n   second */ 2\n3");
+    scanner.scan_tokens();
+    assert!(!scanner.had_error);
+    let lines: Vec<usize> = scanner.tokens.iter().map(|tok| tok.line).collect();
+    assert_eq!(lines, vec![1, 3, 4, 4]);
+
+    // nested comments
+    assert_eq!(Scanner::token_types("/* a /* b */ c */ nil"),
+               vec![TokenType::Nil, TokenType::Eof]);
+}
+
+#[test]
+fn test_unterminated_block_comment() {
+    let errors = tokenize("1 /* a /* b */\n c").unwrap_err();
+    assert_eq!(errors, vec![ScanError {
+        line: 2,
+        message: "Unterminated block comment".to_string(),
+    }]);
+}
+
+#[test]
+fn test_string_escapes() {
+    let cases = [
+        (r#""line1\nline2""#, "line1\nline2"),
+        (r#""a\tb""#, "a\tb"),
+        (r#""a\rb""#, "a\rb"),
+        (r#""say \"hi\"""#, "say \"hi\""),
+        (r#""C:\\dir""#, "C:\\dir"),
+        (r#""\${x}""#, "${x}"),
+    ];
+    for (source, expected) in cases {
+        let tokens = tokenize(source).unwrap();
+        assert_eq!(tokens[0].token.value, Some(Value::String(expected.to_string())));
+        // the lexeme keeps the escape sequences as written
+        assert_eq!(tokens[0].token.lexeme, source);
+    }
+
+    // escapes in the literal parts of an interpolated string, and in
+    // strings nested inside an interpolation
+    let tokens = tokenize(r#""a\t${"\"x\""}\n""#).unwrap();
+    assert_eq!(tokens[0].token.value, Some(Value::Interpolation(vec![
+        StringPart::Literal("a\t".to_string()),
+        StringPart::Code(r#""\"x\"""#.to_string(), 1),
+        StringPart::Literal("\n".to_string()),
+    ])));
+}
+
+#[test]
+fn test_unknown_escape() {
+    let errors = tokenize(r#""a\qb" 1"#).unwrap_err();
+    assert_eq!(errors, vec![ScanError {
+        line: 1,
+        message: r"Unknown escape sequence in string literal: \q".to_string(),
+    }]);
+}
+
+#[test]
+fn test_interpolation_errors() {
+    let errors = tokenize("\"a ${#} b\"\n\"${\n  1 @ 2}\"").unwrap_err();
+    assert_eq!(errors, vec![
+        ScanError { line: 1, message: "Unrecognized character: #".to_string() },
+        ScanError { line: 3, message: "Unrecognized character: @".to_string() },
+    ]);
+
+    let stats = scan_stats("\"a ${#} b\"");
+    assert_eq!(stats.errors.len(), 1);
+    assert_eq!(stats.count(TokenType::Interpolation), 1);
+}
@@ -0,0 +1,4 @@
+pub mod lexer;
+pub mod ast;
+pub mod parser;
+pub mod interpreter;
@@ -1,68 +1,247 @@
 use std::env;
 use std::io;
+use std::io::BufRead;
 use std::io::Write;
-use std::fs::read_to_string;
+use std::fs;
 
-mod lexer;
-mod ast;
-mod parser;
-mod interpreter;
-
-use lexer::Scanner;
-use parser::Parser;
+use treewalk::lexer;
+use treewalk::interpreter;
+use treewalk::lexer::Scanner;
+use treewalk::parser::Parser;
+use treewalk::parser::ParseError;
+use treewalk::interpreter::NumberFormat;
 
 fn main() {
     println!("Lox interpreter");
-    let args: Vec<String> = env::args().skip(1).collect();
-    if args.len() > 1 {
-        println!("Usage: rlox [filename]");
-        std::process::exit(1);
-    }
-    if args.len() == 1 {
-        println!("Processing file: {}", &args[0]);
-        process_file(&args[0]);
+    let options = match parse_args(env::args().skip(1), lexer::use_color()) {
+        Some(options) => options,
+        None => usage()
+    };
+    if let Some(filename) = &options.file {
+        println!("Processing file: {}", filename);
+        process_file(filename, options.color, options.format);
     } else {
         println!("Opening the REPL...");
-        match repl() {
+        let prompts = resolve_prompts(options.prompt.as_deref(), env::var("RLOX_PROMPT").ok());
+        match repl(options.color, options.format, &prompts) {
             Ok(_) => println!("Ok..."),
             Err(_) => println!("There was some error")
         }
     }
 }
 
-fn repl() -> io::Result<()> {
-    print!("> ");
-    io::stdout().flush()?;
-    let mut buffer = String::new();
-    io::stdin().read_line(&mut buffer)?;
-    println!("{}", buffer);
+fn usage() -> ! {
+    println!("Usage: rlox [--no-color] [--scientific] [--prompt PROMPT] [filename]");
+    std::process::exit(1);
+}
 
-    let mut parser = Parser::new(&buffer);
-    let expr = parser.parse();
-    println!("AST: {}", expr);
+#[derive(Debug, PartialEq)]
+struct Options {
+    color: bool,
+    format: NumberFormat,
+    prompt: Option<String>,
+    file: Option<String>,
+}
 
-    Ok(())
+// parse the command line arguments (without the program name); the prompt
+// can be given as --prompt PROMPT or --prompt=PROMPT, so a prompt starting
+// with "--" is still taken as the value of the flag. Returns None for
+// unknown flags, a missing prompt or more than one file name
+fn parse_args(args: impl IntoIterator<Item = String>, color: bool) -> Option<Options> {
+    let mut options = Options { color, format: NumberFormat::Plain, prompt: None, file: None };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if let Some(p) = arg.strip_prefix("--prompt=") {
+            options.prompt = Some(p.to_string());
+            continue;
+        }
+        match arg.as_str() {
+            "--no-color" => options.color = false,
+            "--scientific" => options.format = NumberFormat::scientific(),
+            "--prompt" => options.prompt = Some(args.next()?),
+            _ if arg.starts_with("--") => return None,
+            _ if options.file.is_some() => return None,
+            _ => options.file = Some(arg)
+        }
+    }
+    Some(options)
 }
 
-fn run(contents: &str) {
-    let mut scanner = Scanner::new(contents);
+struct Prompts {
+    main: String,
+    continuation: String,
+}
 
-    scanner.scan_tokens();
+// the REPL prompt is taken from the --prompt flag, then the RLOX_PROMPT
+// environment variable, then the default "> "; the continuation prompt
+// has the same width, with dots in place of the visible characters
+fn resolve_prompts(flag: Option<&str>, env_prompt: Option<String>) -> Prompts {
+    let main = match (flag, env_prompt) {
+        (Some(p), _) => p.to_string(),
+        (None, Some(p)) => p,
+        (None, None) => "> ".to_string()
+    };
+    let continuation = main.chars()
+        .map(|c| if c.is_whitespace() { c } else { '.' })
+        .collect();
+    Prompts { main, continuation }
+}
 
-    for tok in scanner.tokens {
-        println!("Next token: {}", tok);
+// read-eval-print loop: runs until the end of input (Ctrl-D); errors in
+// one line are reported and the loop continues with the next one
+fn repl(color: bool, format: NumberFormat, prompts: &Prompts) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut parser = Parser::new("");
+    parser.set_color(color);
+    loop {
+        // an unfinished expression continues in the next line
+        let prompt = if parser.has_pending() { &prompts.continuation } else { &prompts.main };
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        let buffer = read_logical_line(&mut stdin.lock(), &prompts.continuation)?;
+        if buffer.is_empty() {
+            println!();
+            return Ok(());
+        }
+        if let Some(output) = repl_line(&mut parser, &buffer, color, format) {
+            println!("{}", output);
+        }
+    }
+}
+
+// handle one logical line of REPL input, returning what should be printed:
+// the output of a command or the values of the expressions in the line
+// (there are no statements yet, so every other line has expressions);
+// the line is added to the source already in the parser and parsed
+// incrementally, so an unfinished expression continues in the next line;
+// line numbers in error messages count all the lines in the session
+fn repl_line(parser: &mut Parser, line: &str, color: bool, format: NumberFormat) -> Option<String> {
+    if let Some(output) = repl_command(line, color) {
+        return Some(output);
     }
 
-    if scanner.had_error {
-        println!("*** Errors occurred during lexing.");
+    let result = parser.parse_line(line);
+    if parser.had_error() {
+        // lexical errors were already reported by the scanner; drop the
+        // rest of the expression with the error
+        parser.clear_error();
+        parser.discard_pending();
+        return None;
+    }
+    let exprs = match result {
+        Ok(exprs) => exprs,
+        Err(errors) => {
+            let messages: Vec<String> =
+                errors.iter().map(|e| parse_error_message(e, color)).collect();
+            return Some(messages.join("\n"));
+        }
+    };
+
+    let mut output = vec![];
+    for (expr, &line) in exprs.iter().zip(parser.expression_lines()) {
+        match interpreter::eval_with(expr, format) {
+            Ok(value) => output.push(interpreter::stringify_with(&value, format)),
+            Err(e) => {
+                output.push(lexer::format_error(line, &e.to_string(), color));
+                break;
+            }
+        }
+    }
+    if output.is_empty() {
+        None
     } else {
-        println!("*** No lexical errors detected.")
+        Some(output.join("\n"))
+    }
+}
+
+fn parse_error_message(e: &ParseError, color: bool) -> String {
+    lexer::format_error(e.token.line, &e.to_string(), color)
+}
+
+// read a logical line from the input: a line ending with a backslash
+// continues on the next line (the backslash and newline are removed)
+fn read_logical_line<R: BufRead>(input: &mut R, continuation: &str) -> io::Result<String> {
+    let mut buffer = String::new();
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            break;
+        }
+        match line.trim_end_matches(['\n', '\r']).strip_suffix('\\') {
+            Some(start) => {
+                buffer.push_str(start);
+                print!("{}", continuation);
+                io::stdout().flush()?;
+            },
+            None => {
+                buffer.push_str(&line);
+                break;
+            }
+        }
+    }
+    Ok(buffer)
+}
+
+// REPL meta-commands start with ':'; returns the output of the command,
+// or None if the line is not a command
+fn repl_command(line: &str, color: bool) -> Option<String> {
+    let line = line.trim();
+    if !line.starts_with(':') {
+        return None;
+    }
+
+    let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+    match command {
+        ":ast" => {
+            let mut parser = Parser::new(arg);
+            parser.set_color(color);
+            match parser.parse() {
+                Ok(expr) => Some(format!("{}", expr)),
+                Err(e) => Some(parse_error_message(&e, color))
+            }
+        },
+        _ => Some(format!("Unknown command: {}", command))
+    }
+}
+
+// parse and evaluate every expression in the source, printing the values;
+// nothing is evaluated if there were lexical or syntax errors
+fn run(contents: &str, color: bool, format: NumberFormat) {
+    let mut parser = Parser::new(contents);
+    parser.set_color(color);
+
+    let result = parser.parse_all();
+    if parser.had_error() {
+        println!("*** Errors occurred during lexing.");
+        return;
+    }
+    let exprs = match result {
+        Ok(exprs) => exprs,
+        Err(errors) => {
+            for e in &errors {
+                println!("{}", parse_error_message(e, color));
+            }
+            return;
+        }
+    };
+
+    for (expr, &line) in exprs.iter().zip(parser.expression_lines()) {
+        match interpreter::eval_with(expr, format) {
+            Ok(value) => println!("{}", interpreter::stringify_with(&value, format)),
+            Err(e) => {
+                println!("{}", lexer::format_error(line, &e.to_string(), color));
+                return;
+            }
+        }
     }
 }
 
-fn process_file(fname: &str) {
-    match read_to_string(fname) {
-        Ok(s) => run(&s),
+fn process_file(fname: &str, color: bool, format: NumberFormat) {
+    match fs::read(fname) {
+        Ok(bytes) => match lexer::decode_source(&bytes) {
+            Ok(s) => run(s, color, format),
+            Err(e) => println!("{}", lexer::format_error(e.line, &e.message, color)),
+        },
         Err(e) => println!("Error opening file: {}", e),
     }
 }
@@ -72,15 +251,16 @@ fn parser_test_1() {
     let mut parser = Parser::new("3 + 7 * (48 - 6)");
     //let mut parser = Parser::new("42");
 
-    let expr = parser.parse();
-
-    println!("AST: {}", expr);
+    match parser.parse() {
+        Ok(expr) => println!("AST: {}", expr),
+        Err(e) => println!("{}", e)
+    }
 }
 
 #[allow(dead_code)]
 fn scanner_test_1() {
     let mut scanner =
-        Scanner::new("(/*){ ; +\t -}!// this is a comment\n({.,.!=<>====!!})\nif x == 23");
+        Scanner::new("(/ *){ ; +\t -}!// this is a comment\n({.,.!=<>====!!})\nif x == 23");
 
     scanner.scan_tokens();
 
@@ -94,3 +274,118 @@ fn scanner_test_1() {
         println!("*** No lexical errors detected.")
     }
 }
+
+
+// tests
+
+#[test]
+fn test_line_continuation() {
+    let mut input = io::Cursor::new("1 + \\\n2 * 3\n4\n");
+
+    assert_eq!(read_logical_line(&mut input, "").unwrap(), "1 + 2 * 3\n");
+    assert_eq!(read_logical_line(&mut input, "").unwrap(), "4\n");
+    assert_eq!(read_logical_line(&mut input, "").unwrap(), "");
+}
+
+#[test]
+fn test_resolve_prompts() {
+    let prompts = resolve_prompts(None, None);
+    assert_eq!(prompts.main, "> ");
+    assert_eq!(prompts.continuation, ". ");
+
+    let prompts = resolve_prompts(None, Some("lox> ".to_string()));
+    assert_eq!(prompts.main, "lox> ");
+    assert_eq!(prompts.continuation, ".... ");
+
+    let prompts = resolve_prompts(Some(">>> "), Some("lox> ".to_string()));
+    assert_eq!(prompts.main, ">>> ");
+    assert_eq!(prompts.continuation, "... ");
+}
+
+#[test]
+fn test_parse_args() {
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+    let options = parse_args(args(&["--prompt", "lox> ", "test.lox"]), true).unwrap();
+    assert_eq!(options.prompt, Some("lox> ".to_string()));
+    assert_eq!(options.file, Some("test.lox".to_string()));
+    assert!(options.color);
+
+    let options = parse_args(args(&["--no-color", "--prompt=>> ", "--scientific"]), true).unwrap();
+    assert_eq!(options, Options { color: false, format: NumberFormat::scientific(),
+                                  prompt: Some(">> ".to_string()), file: None });
+
+    // the value of --prompt is taken even if it looks like a flag
+    let options = parse_args(args(&["--prompt", "--> "]), false).unwrap();
+    assert_eq!(options.prompt, Some("--> ".to_string()));
+
+    assert_eq!(parse_args(args(&["--prompt"]), false), None);
+    assert_eq!(parse_args(args(&["--verbose"]), false), None);
+    assert_eq!(parse_args(args(&["a.lox", "b.lox"]), false), None);
+}
+
+#[test]
+fn test_repl_ast_command() {
+    assert_eq!(repl_command(":ast 1 + 2", false), Some("(+ 1 2)".to_string()));
+    assert_eq!(repl_command(":ast -(3 * 4)\n", false),
+               Some("(neg (group (* 3 4)))".to_string()));
+    assert_eq!(repl_command(":what", false), Some("Unknown command: :what".to_string()));
+    assert_eq!(repl_command("1 + 2", false), None);
+}
+
+// feed lines to the REPL and collect what it prints for each of them
+#[cfg(test)]
+fn repl_transcript(lines: &[&str]) -> Vec<Option<String>> {
+    repl_transcript_with(lines, NumberFormat::Plain)
+}
+
+#[cfg(test)]
+fn repl_transcript_with(lines: &[&str], format: NumberFormat) -> Vec<Option<String>> {
+    let mut parser = Parser::new("");
+    lines.iter().map(|line| repl_line(&mut parser, line, false, format)).collect()
+}
+
+#[test]
+fn test_repl_prints_values() {
+    let output = repl_transcript(&["1 + 2\n", "\n", "\"a\" + \"b\"\n", "-\"x\"\n",
+                                   ":ast 1 + 2\n", "3 <? 1\n", "(1 +\n", "2)\n",
+                                   "1 2 3\n", ":ast 1 2\n", "4; 5\n"]);
+    assert_eq!(output, vec![
+        Some("3".to_string()),
+        None,
+        Some("ab".to_string()),
+        Some("Error in line 4: Runtime error: Tried to invert sign of a non-numeric value: String(\"x\")".to_string()),
+        Some("(+ 1 2)".to_string()),
+        Some("1".to_string()),
+        None,
+        Some("3".to_string()),
+        Some("Error in line 8: Expect ';' after expression at '2'".to_string()),
+        Some("Error in line 1: Expect end of expression at '2'".to_string()),
+        Some("4\n5".to_string()),
+    ]);
+}
+
+#[test]
+fn test_repl_lexical_error_in_interpolation() {
+    // the error is reported by the scanner, and nothing is evaluated; the
+    // following lines are not affected
+    assert_eq!(repl_transcript(&["\"a ${#1} b\"\n", "\"a \\${1} b\"\n"]),
+               vec![None, Some("a ${1} b".to_string())]);
+}
+
+#[test]
+fn test_repl_runtime_error_color() {
+    let mut parser = Parser::new("");
+    assert_eq!(repl_line(&mut parser, "1;\n", true, NumberFormat::Plain), Some("1".to_string()));
+    assert_eq!(repl_line(&mut parser, "-nil\n", true, NumberFormat::Plain),
+               Some("\x1b[31mError\x1b[0m in line 2: Runtime error: \
+                     Tried to invert sign of a non-numeric value: Nil".to_string()));
+}
+
+#[test]
+fn test_repl_scientific() {
+    let lines = ["1 / 10000000\n", "1234.5\n", "\"${1 / 10000000}\"\n"];
+    assert_eq!(repl_transcript_with(&lines, NumberFormat::scientific()),
+               vec![Some("1e-7".to_string()), Some("1234.5".to_string()), Some("1e-7".to_string())]);
+    assert_eq!(repl_transcript(&lines[..1]), vec![Some("0.0000001".to_string())]);
+}
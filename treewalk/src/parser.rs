@@ -1,101 +1,295 @@
+use std::fmt;
+
 use crate::lexer::Scanner;
 use crate::lexer::Token;
 use crate::lexer::TokenType;
 use crate::lexer::Value;
+use crate::lexer::StringPart;
 use crate::ast::Expr;
+use crate::ast::BinOp;
+use crate::ast::UnOp;
+use crate::ast::InterpolationPart;
+
+// precedence levels of the operators, as encoded in the parse_* methods
+// (a higher level binds tighter)
+pub fn precedence_of(op: &BinOp) -> u8 {
+    match op {
+        BinOp::Equal | BinOp::NotEqual => 1,                    // parse_equality
+        BinOp::Lt | BinOp::LtEqual | BinOp::Gt | BinOp::GtEqual => 2,   // parse_comparison
+        BinOp::Min | BinOp::Max => 3,                           // parse_min_max
+        BinOp::Plus | BinOp::Minus => 4,                        // parse_term
+        BinOp::Mult | BinOp::Div | BinOp::Mod => 5              // parse_factor
+    }
+}
+
+pub fn unary_precedence_of(op: &UnOp) -> u8 {
+    match op {
+        UnOp::Minus | UnOp::Not => 6                            // parse_unary
+    }
+}
+
+// syntax error: the token where the error was found and a description
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub token: Token,
+    pub message: String
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.token.is_eof() {
+            write!(f, "{} at end", self.message)
+        } else {
+            write!(f, "{} at '{}'", self.message, self.token.lexeme)
+        }
+    }
+}
+
+pub type ParseResult = Result<Expr, ParseError>;
+
+// what to do with an expression at the end of the tokens when parsing
+// incrementally: keep it pending, parse it only if it's complete, or
+// parse it as the end of the input
+#[derive(PartialEq, Clone, Copy)]
+enum AtEnd {
+    Keep,
+    Complete,
+    Finish
+}
 
 pub struct Parser {
     scanner: Scanner,
-    current: usize
+    current: usize,
+    // line where each expression returned by the last parse starts
+    lines: Vec<usize>
 }
 
 impl Parser {
     pub fn new(source: &str) -> Parser {
+        Parser::from_scanner(Scanner::new(source))
+    }
+
+    fn from_scanner(scanner: Scanner) -> Parser {
         Parser {
             current: 0,
-            scanner: Scanner::new(source)
+            lines: vec![],
+            scanner
+        }
+    }
+
+    pub fn set_color(&mut self, color: bool) {
+        self.scanner.set_color(color);
+    }
+
+    // true if the scanner reported lexical errors in the source
+    pub fn had_error(&self) -> bool {
+        self.scanner.had_error
+    }
+
+    // forget about previous lexical errors, to parse more source with
+    // parse_line (e.g. the next line in the REPL)
+    pub fn clear_error(&mut self) {
+        self.scanner.had_error = false;
+    }
+
+    // parse a single expression (optionally followed by a ';'), which
+    // must be the whole source
+    pub fn parse(&mut self) -> ParseResult {
+        self.scanner.scan_tokens();
+        let expr = self.parse_expression()?;
+        self.match_token_types(&vec![TokenType::Semicolon]);
+        if !self.is_at_end() {
+            return Err(self.error("Expect end of expression"));
         }
+        Ok(expr)
     }
 
-    pub fn parse(&mut self) -> Expr {
+    // parse all expressions in the source, in sequence; on errors, returns
+    // all the errors found
+    pub fn parse_all(&mut self) -> Result<Vec<Expr>, Vec<ParseError>> {
         self.scanner.scan_tokens();
-        // TODO: return an option; process the result of parse_expression
-        // (Result<Expr, ParseError>) and return accordingly
-        self.parse_expression()
+        self.parse_remaining(AtEnd::Finish)
+    }
+
+    // incremental parsing: scan only the new source and parse the expressions
+    // in it, resuming from where the previous parse stopped; an expression
+    // at the end that is not terminated by ';' is kept pending, as it may
+    // continue in the next chunk, so parsing chunks and then calling finish
+    // gives the same result as parsing all the source at once (except that
+    // a string or block comment must end in the chunk where it starts)
+    pub fn parse_more(&mut self, source: &str) -> Result<Vec<Expr>, Vec<ParseError>> {
+        self.append(source);
+        self.parse_remaining(AtEnd::Keep)
+    }
+
+    // like parse_more, for the REPL: the end of a line also ends a complete
+    // expression, but an unfinished one is kept pending for the next line
+    pub fn parse_line(&mut self, line: &str) -> Result<Vec<Expr>, Vec<ParseError>> {
+        self.append(line);
+        self.parse_remaining(AtEnd::Complete)
+    }
+
+    // parse the pending tokens at the end of the input
+    pub fn finish(&mut self) -> Result<Vec<Expr>, Vec<ParseError>> {
+        self.parse_remaining(AtEnd::Finish)
+    }
+
+    // the lines where the expressions returned by the last call to parse_all,
+    // parse_more, parse_line or finish start, in the same order
+    pub fn expression_lines(&self) -> &[usize] {
+        &self.lines
+    }
+
+    // true if there are tokens left that were not parsed yet
+    pub fn has_pending(&self) -> bool {
+        !self.is_at_end()
     }
 
-    pub fn parse_expression(&mut self) -> Expr {
+    // drop the pending tokens, e.g. after an error in the REPL
+    pub fn discard_pending(&mut self) {
+        self.current = self.scanner.tokens.len() - 1;
+    }
+
+    // the last token is scanned again with the new source only if it was not
+    // consumed yet, so it can't change under an expression already parsed
+    fn append(&mut self, source: &str) {
+        let rescan_last = self.has_pending();
+        self.scanner.append(source, rescan_last);
+    }
+
+    // expressions are separated by ';' (optional after the last one); after
+    // an error, the parser skips to the next statement boundary and goes on
+    fn parse_remaining(&mut self, at_end: AtEnd) -> Result<Vec<Expr>, Vec<ParseError>> {
+        let mut exprs = vec![];
+        let mut errors = vec![];
+        self.lines.clear();
+        while !self.is_at_end() {
+            let start = self.current;
+            let line = self.scanner.tokens[start].line;
+            match self.parse_expression() {
+                Ok(expr) => {
+                    if self.match_token_types(&vec![TokenType::Semicolon]) {
+                        exprs.push(expr);
+                        self.lines.push(line);
+                    } else if self.is_at_end() {
+                        if at_end == AtEnd::Keep {
+                            self.current = start;
+                            break;
+                        }
+                        exprs.push(expr);
+                        self.lines.push(line);
+                    } else if at_end == AtEnd::Keep && self.at_last_token() {
+                        // the last token may still change with the next chunk
+                        self.current = start;
+                        break;
+                    } else {
+                        errors.push(self.error("Expect ';' after expression"));
+                        self.synchronize();
+                    }
+                },
+                // the expression is not finished, but may continue later
+                Err(e) if (e.token.is_eof() && at_end != AtEnd::Finish) ||
+                          (at_end == AtEnd::Keep && self.at_last_token()) => {
+                    self.current = start;
+                    break;
+                },
+                Err(e) => {
+                    errors.push(e);
+                    self.synchronize();
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(exprs)
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn parse_expression(&mut self) -> ParseResult {
         self.parse_equality()
     }
 
-    fn parse_equality(&mut self) -> Expr {
-        let mut expr = self.parse_comparison();
+    fn parse_equality(&mut self) -> ParseResult {
+        let mut expr = self.parse_comparison()?;
         let eq_ops = vec![TokenType::BangEqual, TokenType::EqualEqual];
         while self.match_token_types(&eq_ops) {
             let op_type = self.previous().tok_type;
-            let right = self.parse_comparison();
+            let right = self.parse_comparison()?;
             expr = Expr::binary_from_token(op_type, expr, right);
         }
-        expr
+        Ok(expr)
     }
 
-    fn parse_comparison(&mut self) -> Expr {
-        let mut expr = self.parse_term();
+    fn parse_comparison(&mut self) -> ParseResult {
+        let mut expr = self.parse_min_max()?;
         let comparison_ops = vec![TokenType::Greater, TokenType::GreaterEqual,
                                   TokenType::Less, TokenType::LessEqual];
         while self.match_token_types(&comparison_ops) {
             let op_type = self.previous().tok_type;
-            let right = self.parse_term();
+            let right = self.parse_min_max()?;
+            expr = Expr::binary_from_token(op_type, expr, right);
+        }
+        Ok(expr)
+    }
+
+    // min (<?) and max (>?) bind tighter than comparisons, looser than terms
+    fn parse_min_max(&mut self) -> ParseResult {
+        let mut expr = self.parse_term()?;
+        let min_max_ops = vec![TokenType::LessQuestion, TokenType::GreaterQuestion];
+        while self.match_token_types(&min_max_ops) {
+            let op_type = self.previous().tok_type;
+            let right = self.parse_term()?;
             expr = Expr::binary_from_token(op_type, expr, right);
         }
-        expr
+        Ok(expr)
     }
 
-    fn parse_term(&mut self) -> Expr {
-        let mut expr = self.parse_factor();
+    fn parse_term(&mut self) -> ParseResult {
+        let mut expr = self.parse_factor()?;
         let term_ops = vec![TokenType::Plus, TokenType::Minus];
         while self.match_token_types(&term_ops) {
             let op_type = self.previous().tok_type;
-            let right = self.parse_factor();
+            let right = self.parse_factor()?;
             expr = Expr::binary_from_token(op_type, expr, right);
         }
-        expr
+        Ok(expr)
     }
 
-    fn parse_factor(&mut self) -> Expr {
-        let mut expr = self.parse_unary();
-        let factor_ops = vec![TokenType::Slash, TokenType::Star];
+    fn parse_factor(&mut self) -> ParseResult {
+        let mut expr = self.parse_unary()?;
+        let factor_ops = vec![TokenType::Slash, TokenType::Star, TokenType::Percent];
         while self.match_token_types(&factor_ops) {
             let op_type = self.previous().tok_type;
-            let right = self.parse_unary();
+            let right = self.parse_unary()?;
             expr = Expr::binary_from_token(op_type, expr, right);
         }
-        expr
+        Ok(expr)
     }
 
-    fn parse_unary(&mut self) -> Expr {
+    fn parse_unary(&mut self) -> ParseResult {
         let unary_ops = vec![TokenType::Bang, TokenType::Minus];
         if self.match_token_types(&unary_ops) {
             let op_type = self.previous().tok_type;
-            let right = self.parse_unary();
-            return Expr::unary_from_token(op_type, right);
+            let right = self.parse_unary()?;
+            return Ok(Expr::unary_from_token(op_type, right));
         }
 
         // if it's not a unary operator, it's a primary
         self.parse_primary()
     }
 
-    fn parse_primary(&mut self) -> Expr {
+    fn parse_primary(&mut self) -> ParseResult {
         if self.match_token_types(&vec![TokenType::False]) {
-            return Expr::false_literal();
+            return Ok(Expr::false_literal());
         }
 
         if self.match_token_types(&vec![TokenType::True]) {
-            return Expr::true_literal();
+            return Ok(Expr::true_literal());
         }
 
         if self.match_token_types(&vec![TokenType::Nil]) {
-            return Expr::nil_literal();
+            return Ok(Expr::nil_literal());
         }
 
         if self.match_token_types(&vec![TokenType::Number, TokenType::String]) {
@@ -104,28 +298,41 @@ impl Parser {
                 Some(Value::String(s)) => Expr::string_literal(s),
                 _ => panic!("Invalid value for token, should never happen!")
             };
-            return e;
+            return Ok(e);
+        }
+
+        if self.match_token_types(&vec![TokenType::Interpolation]) {
+            let parts = match &self.previous().value {
+                Some(Value::Interpolation(parts)) => parts.clone(),
+                _ => panic!("Invalid value for token, should never happen!")
+            };
+            let parts = parts.iter()
+                .map(|part| self.parse_interpolation_part(part))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Expr::Interpolation(parts));
         }
 
         if self.match_token_types(&vec![TokenType::LeftParen]) {
-            let expr = self.parse_expression();
-            self.consume(TokenType::RightParen, "Expect ')' after expression");
-            return Expr::group(expr);
+            let expr = self.parse_expression()?;
+            self.consume(TokenType::RightParen, "Expect ')' after expression")?;
+            return Ok(Expr::group(expr));
         }
 
-        // TODO: report error for unexpected token
-        panic!("Expected expression");
+        Err(self.error("Expect expression"))
     }
 
-    fn consume(&mut self, typ: TokenType, msg: &str) {
+    fn consume(&mut self, typ: TokenType, msg: &str) -> Result<&Token, ParseError> {
         if self.check(typ) {
-            self.advance();   // TODO original code returns the token from advance
-            return;
+            return Ok(self.advance());
         }
 
-        // if the next token does not have the required type, raise an error
-        // TODO: should properly report the error, not panic
-        panic!("{}", msg);
+        // if the next token does not have the required type, it's an error
+        Err(self.error(msg))
+    }
+
+    // error at the current token
+    fn error(&self, msg: &str) -> ParseError {
+        ParseError { token: self.peek().clone(), message: msg.to_string() }
     }
 
     fn match_token_types(&mut self, types: &Vec<TokenType>) -> bool {
@@ -135,7 +342,7 @@ impl Parser {
                 return true;
             }
         }
-        return false;
+        false
     }
 
     fn is_at_end(&self) -> bool {
@@ -143,6 +350,11 @@ impl Parser {
             self.scanner.tokens[self.current].is_eof()
     }
 
+    // true if the current token is the last one before EOF, or EOF
+    fn at_last_token(&self) -> bool {
+        self.current + 2 >= self.scanner.tokens.len()
+    }
+
     fn peek(&self) -> &Token {
         if self.is_at_end() {
             // return last token (assuming it is EOF)
@@ -171,6 +383,33 @@ impl Parser {
         self.previous()
     }
 
+    // the code of each ${...} in an interpolated string is parsed on its own,
+    // by a parser with a scanner for the fragment
+    fn parse_interpolation_part(&mut self, part: &StringPart) -> Result<InterpolationPart, ParseError> {
+        match part {
+            StringPart::Literal(s) => Ok(InterpolationPart::Literal(s.to_string())),
+            StringPart::Code(code, line) => {
+                // lexical errors in the code were already reported when
+                // scanning the string
+                let mut parser = Parser::from_scanner(self.scanner.fragment(code, *line));
+                parser.scanner.scan_tokens();
+                // an error at the end of the code is reported at the string
+                let string_token = self.previous();
+                let expr = parser.parse_expression().map_err(|e| {
+                    if e.token.is_eof() {
+                        ParseError { token: string_token.clone(), message: e.message }
+                    } else {
+                        e
+                    }
+                })?;
+                if !parser.is_at_end() {
+                    return Err(parser.error("Expect '}' after expression in string interpolation"));
+                }
+                Ok(InterpolationPart::Expr(expr))
+            }
+        }
+    }
+
     // advance in the token stream until finding a synchronization point
     fn synchronize(&mut self) {
         self.advance();
@@ -192,19 +431,18 @@ impl Parser {
 }
 
 
+
 // tests
 
 #[test]
 fn test_constant() {
     let mut parser = Parser::new("42");
 
-    assert_eq!(parser.parse(), Expr::number_literal(42.0));
+    assert_eq!(parser.parse(), Ok(Expr::number_literal(42.0)));
 }
 
 #[test]
 fn test_simple_expression_1() {
-    use crate::ast::BinOp;
-
     let mut parser = Parser::new("3 + 7 * (48 - 6)");
 
     let expected = Expr::binary(BinOp::Plus,
@@ -216,5 +454,206 @@ fn test_simple_expression_1() {
                                                               Expr::number_literal(48.0),
                                                               Expr::number_literal(6.0)))));
 
-    assert_eq!(parser.parse(), expected);
+    assert_eq!(parser.parse(), Ok(expected));
+}
+
+
+#[test]
+fn test_modulo() {
+    let mut parser = Parser::new("1 + 7 % 3 * 2");
+    let expected = Expr::binary(BinOp::Plus,
+                                Expr::number_literal(1.0),
+                                Expr::binary(BinOp::Mult,
+                                             Expr::binary(BinOp::Mod,
+                                                          Expr::number_literal(7.0),
+                                                          Expr::number_literal(3.0)),
+                                             Expr::number_literal(2.0)));
+    assert_eq!(parser.parse(), Ok(expected));
+}
+
+#[test]
+fn test_min_max() {
+    let mut parser = Parser::new("3 <? 5 == 3");
+    let expected = Expr::binary(BinOp::Equal,
+                                Expr::binary(BinOp::Min,
+                                             Expr::number_literal(3.0),
+                                             Expr::number_literal(5.0)),
+                                Expr::number_literal(3.0));
+    assert_eq!(parser.parse(), Ok(expected));
+
+    let mut parser = Parser::new("1 + 3 >? 5 < 7");
+    let expected = Expr::binary(BinOp::Lt,
+                                Expr::binary(BinOp::Max,
+                                             Expr::binary(BinOp::Plus,
+                                                          Expr::number_literal(1.0),
+                                                          Expr::number_literal(3.0)),
+                                             Expr::number_literal(5.0)),
+                                Expr::number_literal(7.0));
+    assert_eq!(parser.parse(), Ok(expected));
+}
+
+#[test]
+fn test_precedence() {
+    assert!(precedence_of(&BinOp::Mult) > precedence_of(&BinOp::Plus));
+    assert!(precedence_of(&BinOp::Equal) < precedence_of(&BinOp::Plus));
+    assert!(precedence_of(&BinOp::Equal) < precedence_of(&BinOp::Mult));
+    assert!(precedence_of(&BinOp::Equal) < precedence_of(&BinOp::Lt));
+    assert_eq!(precedence_of(&BinOp::Div), precedence_of(&BinOp::Mult));
+    assert!(unary_precedence_of(&UnOp::Minus) > precedence_of(&BinOp::Mult));
+
+    // the parse shape agrees: the operator with lower precedence is at the root
+    match Parser::new("1 == 2 * 3").parse().unwrap() {
+        Expr::Binary(op, _, _) => assert_eq!(op, BinOp::Equal),
+        e => panic!("Unexpected expression: {}", e)
+    }
+}
+
+#[test]
+fn test_interpolation() {
+    let mut parser = Parser::new("\"sum: ${1 + 2}!\"");
+
+    let expected = Expr::Interpolation(vec![
+        InterpolationPart::Literal("sum: ".to_string()),
+        InterpolationPart::Expr(Expr::binary(BinOp::Plus,
+                                             Expr::number_literal(1.0),
+                                             Expr::number_literal(2.0))),
+        InterpolationPart::Literal("!".to_string()),
+    ]);
+    assert_eq!(parser.parse(), Ok(expected));
+}
+
+#[test]
+fn test_incremental_parse() {
+    let line1 = "3 + 7 * 2;\n";
+    let line2 = "(48 - 6) == 42\n";
+
+    let mut parser = Parser::new("");
+    let mut exprs = parser.parse_more(line1).unwrap();
+    assert_eq!(exprs.len(), 1);
+    exprs.extend(parser.parse_more(line2).unwrap());
+    exprs.extend(parser.finish().unwrap());
+
+    let mut full_parser = Parser::new(&format!("{}{}", line1, line2));
+    assert_eq!(exprs, full_parser.parse_all().unwrap());
+
+    // parse the chunks incrementally and the joined source at once
+    let both = |chunks: &[&str]| {
+        let mut parser = Parser::new("");
+        let mut exprs = vec![];
+        for chunk in chunks {
+            exprs.extend(parser.parse_more(chunk).unwrap());
+        }
+        exprs.extend(parser.finish().unwrap());
+        (exprs, Parser::new(&chunks.concat()).parse_all().unwrap())
+    };
+
+    // an expression split across chunks
+    let (incremental, full) = both(&["1 +", " 2\n"]);
+    assert_eq!(incremental, full);
+    assert_eq!(full, vec![Expr::binary(BinOp::Plus,
+                                       Expr::number_literal(1.0),
+                                       Expr::number_literal(2.0))]);
+    let (incremental, full) = both(&["(1", " + 2", ")", " * 3; 4"]);
+    assert_eq!(incremental, full);
+
+    // a token split at the chunk boundary
+    let (incremental, full) = both(&["12", "3"]);
+    assert_eq!(incremental, full);
+    assert_eq!(full, vec![Expr::number_literal(123.0)]);
+    let (incremental, full) = both(&["1 =", "= 1; 2 <", "= 3"]);
+    assert_eq!(incremental, full);
+    assert_eq!(full.len(), 2);
+}
+
+#[test]
+fn test_parse_line() {
+    let mut parser = Parser::new("");
+
+    // a complete expression at the end of the line is parsed
+    assert_eq!(parser.parse_line("12\n"), Ok(vec![Expr::number_literal(12.0)]));
+
+    // an unfinished one is kept for the next line
+    assert_eq!(parser.parse_line("(1 +\n"), Ok(vec![]));
+    assert!(parser.has_pending());
+    assert_eq!(parser.parse_line("3)\n").map(|exprs| exprs.len()), Ok(1));
+    assert!(!parser.has_pending());
+    assert_eq!(parser.expression_lines(), &[2]);
+
+    // a consumed token is not joined with the next line
+    assert_eq!(parser.parse_line("4"), Ok(vec![Expr::number_literal(4.0)]));
+    assert_eq!(parser.parse_line("5"), Ok(vec![Expr::number_literal(5.0)]));
+
+    parser.parse_line("(").unwrap();
+    parser.discard_pending();
+    assert!(!parser.has_pending());
+    assert_eq!(parser.parse_line("6"), Ok(vec![Expr::number_literal(6.0)]));
+}
+
+#[test]
+fn test_expression_lines() {
+    let mut parser = Parser::new("1; 2;\n\n(3 +\n4)");
+    assert_eq!(parser.parse_all().map(|exprs| exprs.len()), Ok(3));
+    assert_eq!(parser.expression_lines(), &[1, 1, 3]);
+
+    let mut parser = Parser::new("");
+    assert_eq!(parser.parse_more("5;\n6"), Ok(vec![Expr::number_literal(5.0)]));
+    assert_eq!(parser.expression_lines(), &[1]);
+    assert_eq!(parser.finish(), Ok(vec![Expr::number_literal(6.0)]));
+    assert_eq!(parser.expression_lines(), &[2]);
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = Parser::new("(1 + ").parse().unwrap_err();
+    assert_eq!(err.message, "Expect expression");
+    assert!(err.token.is_eof());
+    assert_eq!(err.to_string(), "Expect expression at end");
+
+    let err = Parser::new("(1 + 2").parse().unwrap_err();
+    assert_eq!(err.message, "Expect ')' after expression");
+
+    let err = Parser::new("1 2").parse().unwrap_err();
+    assert_eq!(err.to_string(), "Expect end of expression at '2'");
+    assert_eq!(Parser::new("1;").parse(), Ok(Expr::number_literal(1.0)));
+
+    let err = Parser::new("* 3").parse().unwrap_err();
+    assert_eq!(err.token.tok_type, TokenType::Star);
+    assert_eq!(err.to_string(), "Expect expression at '*'");
+
+    // errors inside an interpolation are reported in the line of the code
+    let err = Parser::new("\n\n\"a ${1 2} b\"").parse().unwrap_err();
+    assert_eq!(err.token.line, 3);
+    assert_eq!(err.message, "Expect '}' after expression in string interpolation");
+}
+
+#[test]
+fn test_error_recovery() {
+    let mut parser = Parser::new("(1 + ;\n2 * 3;\n* 4;\n5");
+    let errors = parser.parse_all().unwrap_err();
+
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].to_string(), "Expect expression at ';'");
+    assert_eq!(errors[0].token.line, 1);
+    assert_eq!(errors[1].to_string(), "Expect expression at '*'");
+    assert_eq!(errors[1].token.line, 3);
+
+    let mut parser = Parser::new("1; 2 + 3;");
+    assert_eq!(parser.parse_all().map(|exprs| exprs.len()), Ok(2));
+
+    let mut parser = Parser::new("1 2 3");
+    let errors = parser.parse_all().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "Expect ';' after expression at '2'");
+}
+
+#[test]
+fn test_interpolation_lexical_errors() {
+    let mut parser = Parser::new("1 +\n2;\n\"a ${\n#1} b\"");
+    parser.set_color(false);
+
+    assert!(parser.parse_all().is_ok());
+    assert!(parser.had_error());
+    assert_eq!(parser.scanner.errors.len(), 1);
+    assert_eq!(parser.scanner.errors[0].line, 4);
+    assert_eq!(parser.scanner.errors[0].message, "Unrecognized character: #");
 }